@@ -0,0 +1,459 @@
+//把easy-fs镜像通过FUSE挂到Linux上，这样就可以直接用普通的工具查看和修改
+//FUSE的根目录ino固定是1，而easy-fs的根目录inode编号是0，所以ino = inode_id + 1
+use easy_fs::{
+    EasyFileSystem,
+    Inode,
+    block_cache_sync_all,
+    MAX_FILE_SIZE,
+    BLOCK_SZ,
+};
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
+};
+use libc::{
+    EEXIST, EFBIG, EINVAL, EISDIR, ENAMETOOLONG, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, EPERM,
+};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//renameat2的flags，RENAME_EXCHANGE不支持
+const RENAME_NOREPLACE: u32 = 1;
+
+//镜像可能被别人改，不敢让内核缓存太久
+const TTL: Duration = Duration::from_secs(1);
+
+pub struct EasyFuse {
+    efs: Arc<spin::Mutex<EasyFileSystem>>,
+    //每个inode现在被打开了几次，和内核里的OSInode一样
+    //nlink到0以后要等最后一次release才回收
+    open_counts: HashMap<u32, usize>,
+}
+
+impl EasyFuse {
+    pub fn new(efs: Arc<spin::Mutex<EasyFileSystem>>) -> Self {
+        Self {
+            efs,
+            open_counts: HashMap::new(),
+        }
+    }
+
+    fn inode(&self, ino: u64) -> Arc<Inode> {
+        let block_device = Arc::clone(&self.efs.lock().block_device);
+        Arc::new(Inode::new((ino - 1) as u32, Arc::clone(&self.efs), block_device))
+    }
+
+    //父目录必须存在而且是目录，名字也不能太长
+    fn dir_and_name<'a>(&self, parent: u64, name: &'a OsStr) -> Result<(Arc<Inode>, &'a str), i32> {
+        let name = name.to_str().ok_or(EINVAL)?;
+        if name.len() > self.efs.lock().name_length_limit() {
+            return Err(ENAMETOOLONG);
+        }
+        let dir = self.inode(parent);
+        if !dir.is_dir() {
+            return Err(ENOTDIR);
+        }
+        Ok((dir, name))
+    }
+
+    fn release_if_unused(&mut self, inode: &Arc<Inode>) {
+        let inode_id = inode.get_my_inode_id().unwrap();
+        if inode.nlink() == 0 && !self.open_counts.contains_key(&inode_id) {
+            inode.reclaim();
+        }
+    }
+}
+
+fn to_system_time(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+fn attr(inode: &Inode) -> FileAttr {
+    let size = inode.size() as u64;
+    let (atime, mtime, ctime) = inode.times();
+    let (uid, gid) = inode.owner();
+    let kind = if inode.is_dir() {
+        FileType::Directory
+    } else if inode.is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::RegularFile
+    };
+    FileAttr {
+        ino: inode.get_my_inode_id().unwrap() as u64 + 1,
+        size,
+        blocks: (size + 511) / 512,
+        atime: to_system_time(atime),
+        mtime: to_system_time(mtime),
+        ctime: to_system_time(ctime),
+        crtime: to_system_time(ctime),
+        kind,
+        perm: inode.mode(),
+        nlink: inode.nlink(),
+        uid,
+        gid,
+        rdev: 0,
+        blksize: BLOCK_SZ as u32,
+        padding: 0,
+        flags: 0,
+    }
+}
+
+impl Filesystem for EasyFuse {
+    fn destroy(&mut self, _req: &Request<'_>) {
+        block_cache_sync_all();
+    }
+
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let (dir, name) = match self.dir_and_name(parent, name) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        match dir.find(name) {
+            Some(inode) => reply.entry(&TTL, &attr(&inode), 0),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        reply.attr(&TTL, &attr(&self.inode(ino)));
+    }
+
+    //支持改权限、属主和文件的大小，O_TRUNC和truncate都会走到这里
+    //能不能改由内核按DefaultPermissions检查过了
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<std::time::SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<std::time::SystemTime>,
+        _chgtime: Option<std::time::SystemTime>,
+        _bkuptime: Option<std::time::SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let inode = self.inode(ino);
+        match size {
+            Some(size) if size > MAX_FILE_SIZE as u64 => return reply.error(EFBIG),
+            Some(size) if size as u32 == inode.size() => {}
+            Some(_) if inode.is_dir() => return reply.error(EISDIR),
+            Some(size) if inode.truncate(size as u32) => {}
+            Some(_) => return reply.error(ENOSPC),
+            None => {}
+        }
+        if let Some(mode) = mode {
+            inode.chmod(mode as u16);
+        }
+        if uid.is_some() || gid.is_some() {
+            let (old_uid, old_gid) = inode.owner();
+            inode.chown(uid.unwrap_or(old_uid), gid.unwrap_or(old_gid));
+        }
+        reply.attr(&TTL, &attr(&inode));
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let (dir, name) = match self.dir_and_name(parent, name) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        match dir.create_dir_as(name, (mode & !umask) as u16, req.uid(), req.gid()) {
+            Some(inode) => reply.entry(&TTL, &attr(&inode), 0),
+            None => reply.error(EEXIST),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let (dir, name) = match self.dir_and_name(parent, name) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        match dir.find(name) {
+            Some(inode) if inode.is_dir() => return reply.error(EISDIR),
+            Some(_) => {}
+            None => return reply.error(ENOENT),
+        }
+        match dir.unlink(name) {
+            Some(inode) => {
+                self.release_if_unused(&inode);
+                reply.ok();
+            }
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let (dir, name) = match self.dir_and_name(parent, name) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        match dir.find(name) {
+            Some(inode) if !inode.is_dir() => return reply.error(ENOTDIR),
+            Some(_) => {}
+            None => return reply.error(ENOENT),
+        }
+        match dir.remove_dir(name) {
+            Some(inode) => {
+                self.release_if_unused(&inode);
+                reply.ok();
+            }
+            None => reply.error(ENOTEMPTY),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        if flags & !RENAME_NOREPLACE != 0 {
+            return reply.error(EINVAL);
+        }
+        let (dir, name) = match self.dir_and_name(parent, name) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        let (new_dir, new_name) = match self.dir_and_name(newparent, newname) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        let src = match dir.find(name) {
+            Some(inode) => inode,
+            None => return reply.error(ENOENT),
+        };
+        match dir.rename(name, &new_dir, new_name, flags & RENAME_NOREPLACE != 0) {
+            Some(replaced) => {
+                if let Some(inode) = replaced {
+                    self.release_if_unused(&inode);
+                }
+                reply.ok();
+            }
+            //easy-fs只说失败了，这里把原因猜出来
+            None => reply.error(match new_dir.find(new_name) {
+                Some(_) if flags & RENAME_NOREPLACE != 0 => EEXIST,
+                Some(dst) if dst.is_dir() && src.is_dir() => ENOTEMPTY,
+                Some(dst) if dst.is_dir() => EISDIR,
+                Some(_) if src.is_dir() => ENOTDIR,
+                _ => EINVAL,
+            }),
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        link: &Path,
+        reply: ReplyEntry,
+    ) {
+        let (dir, name) = match self.dir_and_name(parent, name) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        let target = match link.to_str() {
+            Some(target) => target,
+            None => return reply.error(EINVAL),
+        };
+        match dir.create_symlink_as(name, target, req.uid(), req.gid()) {
+            Some(inode) => reply.entry(&TTL, &attr(&inode), 0),
+            None => reply.error(EEXIST),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        match self.inode(ino).read_link() {
+            Some(target) => reply.data(target.as_bytes()),
+            None => reply.error(EINVAL),
+        }
+    }
+
+    fn link(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        let (dir, name) = match self.dir_and_name(newparent, newname) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        let target = self.inode(ino);
+        if target.is_dir() {
+            return reply.error(EPERM);
+        }
+        match dir.link(name, &target) {
+            Some(inode) => reply.entry(&TTL, &attr(&inode), 0),
+            None => reply.error(EEXIST),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        *self.open_counts.entry((ino - 1) as u32).or_insert(0) += 1;
+        reply.opened(0, 0);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let mut buffer = vec![0u8; size as usize];
+        let len = self.inode(ino).read_at(offset as usize, &mut buffer);
+        reply.data(&buffer[..len]);
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        //easy-fs分配不到块会直接panic，镜像也就脏了，写之前先确认放得下
+        let inode = self.inode(ino);
+        let end = offset as u64 + data.len() as u64;
+        if end > MAX_FILE_SIZE as u64 {
+            return reply.error(EFBIG);
+        }
+        let size = inode.size();
+        if end > size as u64 {
+            let needed = EasyFileSystem::blocks_for_size(end as u32) - EasyFileSystem::blocks_for_size(size);
+            if needed as usize > self.efs.lock().free_data_blocks() {
+                return reply.error(ENOSPC);
+            }
+        }
+        let len = inode.write_at(offset as usize, data);
+        reply.written(len as u32);
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        let inode_id = (ino - 1) as u32;
+        if let Some(count) = self.open_counts.get_mut(&inode_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.open_counts.remove(&inode_id);
+                let inode = self.inode(ino);
+                self.release_if_unused(&inode);
+            }
+        }
+        reply.ok();
+    }
+
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyEmpty,
+    ) {
+        block_cache_sync_all();
+        reply.ok();
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let dir = self.inode(ino);
+        if !dir.is_dir() {
+            return reply.error(ENOTDIR);
+        }
+        //offset是下一项的下标
+        for (i, name) in dir.ls().into_iter().enumerate().skip(offset as usize) {
+            let inode = match dir.find(name.as_str()) {
+                Some(inode) => inode,
+                None => continue,
+            };
+            let kind = attr(&inode).kind;
+            let child_ino = inode.get_my_inode_id().unwrap() as u64 + 1;
+            if reply.add(child_ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let (dir, name) = match self.dir_and_name(parent, name) {
+            Ok(pair) => pair,
+            Err(e) => return reply.error(e),
+        };
+        match dir.create_as(name, (mode & !umask) as u16, req.uid(), req.gid()) {
+            Some(inode) => {
+                *self.open_counts.entry(inode.get_my_inode_id().unwrap()).or_insert(0) += 1;
+                reply.created(&TTL, &attr(&inode), 0, 0, 0);
+            }
+            None => reply.error(EEXIST),
+        }
+    }
+}
+
+//一直阻塞到被umount为止
+pub fn mount(efs: Arc<spin::Mutex<EasyFileSystem>>, mountpoint: &str) -> std::io::Result<()> {
+    let options = [
+        MountOption::FSName(String::from("easy-fs")),
+        MountOption::DefaultPermissions,
+    ];
+    let result = fuser::mount2(EasyFuse::new(efs), mountpoint, &options);
+    block_cache_sync_all();
+    result
+}
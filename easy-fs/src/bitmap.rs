@@ -67,6 +67,41 @@ impl Bitmap {
         });
     }
 
+    pub fn test(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(
+            block_pos + self.start_block_id,
+            Arc::clone(block_device)
+        ).lock().read(0, |bitmap_block: &BitmapBlock| {
+            bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0
+        })
+    }
+
+    //fsck修复的时候用，直接把某一位标记成已分配
+    pub fn set(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(
+            block_pos + self.start_block_id,
+            Arc::clone(block_device)
+        ).lock().modify(0, |bitmap_block: &mut BitmapBlock| {
+            bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+        });
+    }
+
+    //已经分配出去的位数
+    pub fn count_allocated(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        (0..self.blocks)
+            .map(|block_id| {
+                get_block_cache(
+                    block_id + self.start_block_id,
+                    Arc::clone(block_device)
+                ).lock().read(0, |bitmap_block: &BitmapBlock| {
+                    bitmap_block.iter().map(|bits64| bits64.count_ones() as usize).sum::<usize>()
+                })
+            })
+            .sum()
+    }
+
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
     }
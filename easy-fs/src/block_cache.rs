@@ -2,11 +2,15 @@ use super::{
     BLOCK_SZ,
     BlockDevice,
 };
-use alloc::collections::VecDeque;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use alloc::sync::Arc;
 use lazy_static::*;
 use spin::Mutex;
 
+//cache放在最前面，和整个结构体一样按8字节对齐，
+//这样get_ref拿出来的DiskInode里的u64字段才是对齐的
+#[repr(C)]
 pub struct BlockCache {
     cache: [u8; BLOCK_SZ],
     block_id: usize,
@@ -60,6 +64,10 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
+    pub fn is_dirty(&self) -> bool {
+        self.modified
+    }
+
     pub fn sync(&mut self) {
         if self.modified {
             self.modified = false;
@@ -74,16 +82,41 @@ impl Drop for BlockCache {
     }
 }
 
-const BLOCK_CACHE_SIZE: usize = 16;
+//默认能缓存多少个块，可以用set_block_cache_capacity改
+const BLOCK_CACHE_SIZE: usize = 64;
+
+//缓存里的块都拿着设备的Arc，所以设备的地址在块被淘汰之前不会被别的设备复用
+type CacheKey = (usize, usize);
+
+fn cache_key(block_id: usize, block_device: &Arc<dyn BlockDevice>) -> CacheKey {
+    (Arc::as_ptr(block_device) as *const u8 as usize, block_id)
+}
 
 //这个类型管理了用哪些、不用哪些
+//按(块设备, 块号)建索引，按最近一次访问的先后做LRU淘汰
+//同时打开好几个镜像（比如easy-fs-fuse的测试）的时候块号会重复，所以要带上设备
+//写回是懒的：改过的块只在被淘汰、sync_all或者脏块太多的时候才写回磁盘
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    capacity: usize,
+    //(设备, 块号) -> (最近一次访问的时间, 缓存)
+    caches: BTreeMap<CacheKey, (u64, Arc<Mutex<BlockCache>>)>,
+    //最近一次访问的时间 -> (设备, 块号)，最前面的就是最久没用过的
+    lru: BTreeMap<u64, CacheKey>,
+    tick: u64,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
-        Self { queue: VecDeque::new() }
+        Self::with_capacity(BLOCK_CACHE_SIZE)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            caches: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+        }
     }
 
     pub fn get_block_cache(
@@ -91,29 +124,78 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue
-            .iter()
-            .find(|pair| pair.0 == block_id) {
-                Arc::clone(&pair.1)
-        } else {
-            // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self.queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1) {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
+        self.tick += 1;
+        let tick = self.tick;
+        let key = cache_key(block_id, &block_device);
+        if let Some((last_used, cache)) = self.caches.get_mut(&key) {
+            self.lru.remove(last_used);
+            *last_used = tick;
+            self.lru.insert(tick, key);
+            return Arc::clone(cache);
+        }
+        // substitute
+        self.shrink_to(self.capacity - 1);
+        self.write_back_excess();
+        // load block into mem
+        let block_cache = Arc::new(Mutex::new(
+            BlockCache::new(block_id, Arc::clone(&block_device))
+        ));
+        self.caches.insert(key, (tick, Arc::clone(&block_cache)));
+        self.lru.insert(tick, key);
+        block_cache
+    }
+
+    //正在被别人用着的块不能淘汰，也不能去拿它的锁（拿着它的人可能正在等我们）
+    fn is_pinned(cache: &Arc<Mutex<BlockCache>>) -> bool {
+        Arc::strong_count(cache) > 1
+    }
+
+    //从最久没用过的开始淘汰，直到只剩下target个
+    //全都被占着的时候就先超出容量，等以后有块空出来了再缩回去，而不是panic
+    fn shrink_to(&mut self, target: usize) {
+        if self.caches.len() <= target {
+            return;
+        }
+        let mut victims: Vec<(u64, CacheKey)> = Vec::new();
+        for (&last_used, key) in self.lru.iter() {
+            if self.caches.len() - victims.len() <= target {
+                break;
             }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(
-                BlockCache::new(block_id, Arc::clone(&block_device))
-            ));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+            if !Self::is_pinned(&self.caches[key].1) {
+                victims.push((last_used, *key));
+            }
+        }
+        for (last_used, key) in victims {
+            self.lru.remove(&last_used);
+            // dropping the last reference writes the block back
+            self.caches.remove(&key);
+        }
+    }
+
+    //脏块超过容量的一半就先把最久没用过的那些写回去
+    //这样写得多的时候每次换入都要顺便写盘，而不是攒到最后一下子写或者丢掉
+    fn write_back_excess(&mut self) {
+        let dirty_limit = self.capacity / 2;
+        let dirty: Vec<&Arc<Mutex<BlockCache>>> = self.lru.values()
+            .map(|key| &self.caches[key].1)
+            .filter(|cache| !Self::is_pinned(cache) && cache.lock().is_dirty())
+            .collect();
+        if dirty.len() > dirty_limit {
+            for cache in dirty.iter().take(dirty.len() - dirty_limit) {
+                cache.lock().sync();
+            }
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.shrink_to(self.capacity);
+    }
+
+    //把缓存里所有改过的块都写回磁盘
+    pub fn sync_all(&self) {
+        for (_, cache) in self.caches.values() {
+            cache.lock().sync();
         }
     }
 }
@@ -129,4 +211,18 @@ pub fn get_block_cache(
     block_device: Arc<dyn BlockDevice>
 ) -> Arc<Mutex<BlockCache>> {
     BLOCK_CACHE_MANAGER.lock().get_block_cache(block_id, block_device)
-}
\ No newline at end of file
+}
+
+pub fn block_cache_sync_all() {
+    BLOCK_CACHE_MANAGER.lock().sync_all();
+}
+
+//块缓存最多放多少个块，变小的时候多出来的块马上写回并淘汰
+pub fn set_block_cache_capacity(capacity: usize) {
+    BLOCK_CACHE_MANAGER.lock().set_capacity(capacity);
+}
+
+//现在缓存了多少个块，测试用
+pub fn block_cache_len() -> usize {
+    BLOCK_CACHE_MANAGER.lock().caches.len()
+}
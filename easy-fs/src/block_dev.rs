@@ -1,6 +1,22 @@
 use core::any::Any;
+use lazy_static::*;
+use spin::Mutex;
 
 pub trait BlockDevice : Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     fn write_block(&self, block_id: usize, buf: &[u8]);
 }
+
+lazy_static! {
+    //inode里的时间戳从这里取，单位是毫秒，从什么时候开始算由使用者决定
+    //内核用开机以来的时间，easy-fs-fuse用UNIX时间；没设置的话都是0
+    static ref CLOCK: Mutex<fn() -> u64> = Mutex::new(|| 0);
+}
+
+pub fn set_clock(clock: fn() -> u64) {
+    *CLOCK.lock() = clock;
+}
+
+pub fn now_ms() -> u64 {
+    (CLOCK.lock())()
+}
@@ -0,0 +1,227 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::string::String;
+use alloc::format;
+use spin::Mutex;
+use super::{
+    EasyFileSystem,
+    SuperBlock,
+    DiskInode,
+    DIRENT_SZ,
+    encode_dirent,
+    parse_dirents,
+    get_block_cache,
+};
+
+//离线检查（fsck）的结果
+//problems里面每一条是发现的一个问题，repaired表示已经尝试修复过了
+pub struct CheckReport {
+    pub problems: Vec<String>,
+    pub repaired: bool,
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl EasyFileSystem {
+    //从根目录出发把能走到的inode和数据块都找一遍，再和两个位图、nlink对照
+    //repair为true时顺便修复：
+    //  悬空的目录项清空，nlink改成实际的目录项个数，
+    //  位图按照实际的占用情况改过来，指针越界的inode截断成空文件
+    //多个inode共用同一个数据块的情况只报告，不修复
+    pub fn check(efs: &Arc<Mutex<Self>>, repair: bool) -> CheckReport {
+        //每一处修复单独一个事务，整个修复放在一个事务里日志可能放不下；
+        //修到一半崩溃了镜像也不会变得更乱，再检查一遍就能接着修
+        let mut fs = efs.lock();
+        let long_names = fs.long_names;
+        let block_device = Arc::clone(&fs.block_device);
+        let mut problems: Vec<String> = Vec::new();
+
+        let geometry_ok = get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                1 + super_block.inode_bitmap_blocks
+                    + super_block.inode_area_blocks
+                    + super_block.data_bitmap_blocks
+                    + super_block.data_area_blocks
+                    + super_block.journal_blocks
+                    == super_block.total_blocks
+            });
+        if !geometry_ok {
+            problems.push(String::from("super block: area sizes do not add up to total_blocks"));
+        }
+
+        let inode_count = fs.inode_bitmap.maximum();
+        let data_start = fs.data_area_start_block;
+        let data_count = fs.data_area_blocks as usize;
+        let data_end = data_start + fs.data_area_blocks;
+        let in_range = |block_id: u32| block_id >= data_start && block_id < data_end;
+
+        //links[id]：有多少个目录项指向它，根目录算一个
+        let mut links = vec![0u32; inode_count];
+        let mut visited = vec![false; inode_count];
+        //owner[bit]：哪个inode占用了这个数据块
+        let mut owner: Vec<Option<u32>> = vec![None; data_count];
+        //(inode, 父目录)
+        let mut stack: Vec<(u32, u32)> = vec![(0, 0)];
+        links[0] = 1;
+        visited[0] = true;
+
+        while let Some((inode_id, parent_id)) = stack.pop() {
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+            let inode_block = get_block_cache(block_id as usize, Arc::clone(&block_device));
+            let blocks = inode_block.lock().read(block_offset, |disk_inode: &DiskInode| {
+                disk_inode.all_blocks(&block_device, &in_range)
+            });
+            let blocks = match blocks {
+                Some(blocks) => blocks,
+                None => {
+                    problems.push(format!("inode {}: block pointer out of range", inode_id));
+                    if repair {
+                        //越界的指针没法相信，只能整个截断，剩下的块交给位图修复去回收
+                        let _tx = fs.transaction();
+                        inode_block.lock().modify(block_offset, |disk_inode: &mut DiskInode| {
+                            disk_inode.size = 0;
+                            disk_inode.direct.iter_mut().for_each(|v| *v = 0);
+                            disk_inode.indirect1 = 0;
+                            disk_inode.indirect2 = 0;
+                        });
+                    }
+                    Vec::new()
+                }
+            };
+            for block in blocks {
+                let bit = (block - data_start) as usize;
+                match owner[bit] {
+                    Some(other) => problems.push(format!(
+                        "block {}: used by both inode {} and inode {}", block, other, inode_id
+                    )),
+                    None => owner[bit] = Some(inode_id),
+                }
+            }
+
+            let dirents = inode_block.lock().read(block_offset, |disk_inode: &DiskInode| {
+                if !disk_inode.is_dir() {
+                    return Vec::new();
+                }
+                let mut data = vec![0u8; disk_inode.size as usize];
+                disk_inode.read_at(0, &mut data, &block_device);
+                parse_dirents(&data, long_names)
+            });
+            for dirent in dirents.iter() {
+                let name = dirent.name.as_str();
+                let target = dirent.inode_number;
+                //老镜像的根目录没有.和..，所以只检查存在的
+                let expected = match name {
+                    "" => continue,
+                    "." => Some(inode_id),
+                    ".." => Some(parent_id),
+                    _ => None,
+                };
+                //修复的时候整个目录项原地重写，占的槽位数不变
+                let mut fixed: Option<Vec<u8>> = None;
+                if let Some(expected) = expected {
+                    if target != expected {
+                        problems.push(format!(
+                            "inode {}: \"{}\" points to inode {} instead of {}", inode_id, name, target, expected
+                        ));
+                        fixed = Some(encode_dirent(name, expected, dirent.slots, long_names));
+                    }
+                } else if target as usize >= inode_count || !fs.inode_bitmap.test(&block_device, target as usize) {
+                    problems.push(format!(
+                        "inode {}: dirent \"{}\" points to free inode {}", inode_id, name, target
+                    ));
+                    fixed = Some(encode_dirent("", 0, dirent.slots, long_names));
+                } else if visited[target as usize] && Self::check_is_dir(&fs, target) {
+                    problems.push(format!(
+                        "inode {}: dirent \"{}\" is another name of directory {}", inode_id, name, target
+                    ));
+                    fixed = Some(encode_dirent("", 0, dirent.slots, long_names));
+                } else {
+                    links[target as usize] += 1;
+                    if !visited[target as usize] {
+                        visited[target as usize] = true;
+                        stack.push((target, inode_id));
+                    }
+                }
+                if let Some(fixed) = fixed {
+                    if repair {
+                        let _tx = fs.transaction();
+                        inode_block.lock().modify(block_offset, |disk_inode: &mut DiskInode| {
+                            disk_inode.write_at(dirent.slot * DIRENT_SZ, &fixed, &block_device);
+                        });
+                    }
+                }
+            }
+        }
+
+        // link counts
+        for inode_id in (0..inode_count).filter(|&id| visited[id]) {
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id as u32);
+            let inode_block = get_block_cache(block_id as usize, Arc::clone(&block_device));
+            let nlink = inode_block.lock().read(block_offset, |disk_inode: &DiskInode| disk_inode.nlink);
+            if nlink != links[inode_id] {
+                problems.push(format!(
+                    "inode {}: nlink is {} but {} dirents point to it", inode_id, nlink, links[inode_id]
+                ));
+                if repair {
+                    let _tx = fs.transaction();
+                    inode_block.lock().modify(block_offset, |disk_inode: &mut DiskInode| {
+                        disk_inode.nlink = links[inode_id];
+                    });
+                }
+            }
+        }
+
+        // inode bitmap
+        for inode_id in 0..inode_count {
+            let allocated = fs.inode_bitmap.test(&block_device, inode_id);
+            if allocated && !visited[inode_id] {
+                problems.push(format!("inode {}: allocated but unreachable", inode_id));
+                if repair {
+                    let _tx = fs.transaction();
+                    fs.dealloc_inode(inode_id as u32);
+                }
+            } else if !allocated && visited[inode_id] {
+                problems.push(format!("inode {}: in use but free in bitmap", inode_id));
+                if repair {
+                    let _tx = fs.transaction();
+                    fs.inode_bitmap.set(&block_device, inode_id);
+                }
+            }
+        }
+
+        // data bitmap
+        for bit in 0..data_count {
+            let block_id = data_start + bit as u32;
+            let allocated = fs.data_bitmap.test(&block_device, bit);
+            if allocated && owner[bit].is_none() {
+                problems.push(format!("block {}: allocated but unreachable", block_id));
+                if repair {
+                    let _tx = fs.transaction();
+                    fs.dealloc_data(block_id);
+                }
+            } else if !allocated && owner[bit].is_some() {
+                problems.push(format!("block {}: in use but free in bitmap", block_id));
+                if repair {
+                    let _tx = fs.transaction();
+                    fs.data_bitmap.set(&block_device, bit);
+                }
+            }
+        }
+
+        let repaired = repair && !problems.is_empty();
+        CheckReport { problems, repaired }
+    }
+
+    fn check_is_dir(fs: &EasyFileSystem, inode_id: u32) -> bool {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&fs.block_device))
+            .lock()
+            .read(block_offset, |disk_inode: &DiskInode| disk_inode.is_dir())
+    }
+}
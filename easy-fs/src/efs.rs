@@ -1,4 +1,5 @@
 use alloc::sync::Arc;
+use alloc::vec;
 use spin::Mutex;
 use super::{
     BlockDevice,
@@ -7,7 +8,19 @@ use super::{
     DiskInode,
     DiskInodeType,
     Inode,
+    DIRENT_SZ,
+    ROOT_DIR_MODE,
+    EFS_VERSION_LONG_NAME,
+    encode_dirent,
+    dirent_slots,
+    NAME_LENGTH_LIMIT,
+    LONG_NAME_LENGTH_LIMIT,
+    JournalDevice,
+    Transaction,
+    DEFAULT_JOURNAL_BLOCKS,
+    MIN_JOURNAL_BLOCKS,
     get_block_cache,
+    block_cache_sync_all,
 };
 use crate::BLOCK_SZ;
 
@@ -17,27 +30,59 @@ pub struct EasyFileSystem {
     pub block_device: Arc<dyn BlockDevice>,
     pub inode_bitmap: Bitmap,
     pub data_bitmap: Bitmap,
-    inode_area_start_block: u32,
-    data_area_start_block: u32,
+    pub inode_area_start_block: u32,
+    pub data_area_start_block: u32,
+    pub data_area_blocks: u32,
+    //目录项是不是长名字的格式，由超级块里的版本号决定
+    pub long_names: bool,
+    //block_device其实就是它，开事务要用
+    pub journal: Arc<JournalDevice>,
 }
 
 type DataBlock = [u8; BLOCK_SZ];
 
 impl EasyFileSystem {
+    //新建的镜像默认用长名字的目录项，最后留出DEFAULT_JOURNAL_BLOCKS块做日志
     pub fn create(
         block_device: Arc<dyn BlockDevice>,
         total_blocks: u32,
         inode_bitmap_blocks: u32,
+    ) -> Arc<Mutex<Self>> {
+        Self::create_with_options(
+            block_device,
+            total_blocks,
+            inode_bitmap_blocks,
+            EFS_VERSION_LONG_NAME,
+            DEFAULT_JOURNAL_BLOCKS,
+        )
+    }
+
+    //version为0就是老格式，名字最多27个字节
+    //journal_blocks为0就不要日志，和老镜像一样，否则至少要有MIN_JOURNAL_BLOCKS块
+    pub fn create_with_options(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        version: u32,
+        journal_blocks: u32,
     ) -> Arc<Mutex<Self>> {
         // calculate block size of areas & create bitmaps
+        assert!(
+            journal_blocks == 0 || journal_blocks >= MIN_JOURNAL_BLOCKS,
+            "Journal too small for easy-fs!"
+        );
+        let (inode_area_blocks, data_bitmap_blocks, data_area_blocks) = total_blocks
+            .checked_sub(journal_blocks)
+            .and_then(|fs_blocks| Self::layout(fs_blocks, inode_bitmap_blocks))
+            .expect("Image too small for easy-fs!");
+        let journal = Arc::new(JournalDevice::new(
+            block_device,
+            total_blocks - journal_blocks,
+            journal_blocks,
+        ));
+        let block_device: Arc<dyn BlockDevice> = journal.clone();
         let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
-        let inode_num = inode_bitmap.maximum();
-        let inode_area_blocks =
-            ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SZ - 1) / BLOCK_SZ) as u32;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
-        let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
-        let data_area_blocks = data_total_blocks - data_bitmap_blocks;
         let data_bitmap = Bitmap::new(
             (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
             data_bitmap_blocks as usize,
@@ -48,6 +93,9 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            data_area_blocks,
+            long_names: version >= EFS_VERSION_LONG_NAME,
+            journal,
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -70,12 +118,16 @@ impl EasyFileSystem {
                 inode_area_blocks,
                 data_bitmap_blocks,
                 data_area_blocks,
+                version,
+                journal_blocks,
             );
         });
         // write back immediately
         // create a inode for root node "/"
         assert_eq!(efs.alloc_inode(), 0);
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
+        // "." and ".." of "/" both point to itself
+        let dirent_block = efs.alloc_data().expect("Image too small for easy-fs!");
         get_block_cache(
             root_inode_block_id as usize,
             Arc::clone(&block_device)
@@ -83,21 +135,84 @@ impl EasyFileSystem {
         .lock()
         .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
             disk_inode.initialize(DiskInodeType::Directory);
+            //根目录和/tmp一样谁都可以在里面新建，但是只能删改自己的东西（sticky）
+            disk_inode.mode = ROOT_DIR_MODE;
+            disk_inode.increase_size(2 * DIRENT_SZ as u32, vec![dirent_block], &block_device);
+            disk_inode.write_at(0, &encode_dirent(".", 0, 1, efs.long_names), &block_device);
+            disk_inode.write_at(DIRENT_SZ, &encode_dirent("..", 0, 1, efs.long_names), &block_device);
         });
+        block_cache_sync_all();
         Arc::new(Mutex::new(efs))
     }
 
+    //给定总块数和inode位图的块数，算出
+    //(inode_area_blocks, data_bitmap_blocks, data_area_blocks)
+    //超级块里面放不下，或者连根目录的一个数据块都放不下就返回None
+    pub fn layout(total_blocks: u32, inode_bitmap_blocks: u32) -> Option<(u32, u32, u32)> {
+        if inode_bitmap_blocks == 0 || inode_bitmap_blocks >= total_blocks {
+            return None;
+        }
+        let inode_num = inode_bitmap_blocks as u64 * (BLOCK_SZ * 8) as u64;
+        let inode_area_blocks =
+            (inode_num * core::mem::size_of::<DiskInode>() as u64 + BLOCK_SZ as u64 - 1) / BLOCK_SZ as u64;
+        let inode_total_blocks = inode_bitmap_blocks as u64 + inode_area_blocks;
+        let data_total_blocks = (total_blocks as u64).checked_sub(1 + inode_total_blocks)?;
+        let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
+        let data_area_blocks = data_total_blocks - data_bitmap_blocks;
+        if data_area_blocks == 0 {
+            return None;
+        }
+        Some((inode_area_blocks as u32, data_bitmap_blocks as u32, data_area_blocks as u32))
+    }
+
+    //一个位图块能管4096个inode
+    pub fn max_inodes(inode_bitmap_blocks: u32) -> u32 {
+        inode_bitmap_blocks * (BLOCK_SZ * 8) as u32
+    }
+
+    //一个size字节的文件要占多少个数据块（包括索引块）
+    pub fn blocks_for_size(size: u32) -> u32 {
+        DiskInode::total_blocks(size)
+    }
+
+    //EasyFileSystem::open遇到不合法的镜像会直接panic，想先问一下就用这个
+    //最早的镜像open的时候会被转换，也算合法
+    pub fn is_valid_image(block_device: &Arc<dyn BlockDevice>) -> bool {
+        get_block_cache(0, Arc::clone(block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.is_valid())
+            || Self::is_baseline_image(block_device)
+    }
+
+    //inode布局比现在老、又没法转换的镜像，is_valid_image对它返回false
+    pub fn is_old_layout_image(block_device: &Arc<dyn BlockDevice>) -> bool {
+        get_block_cache(0, Arc::clone(block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.is_old_layout())
+            && !Self::is_baseline_image(block_device)
+    }
+
     //只要把编号为0的超级块读入
+    //日志里如果有提交了但是没写完的事务，先把它重放一遍
+    //最早的打包工具做出来的镜像先原地转换成现在的inode布局
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+        let baseline = Self::is_baseline_image(&block_device);
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
+        let mut efs = get_block_cache(0, Arc::clone(&block_device))
             .lock()
             .read(0, |super_block: &SuperBlock| {
-                assert!(super_block.is_valid(), "Error loading EFS!");
+                assert!(baseline || !super_block.is_old_layout(), "EFS image has an old inode layout!");
+                assert!(baseline || super_block.is_valid(), "Error loading EFS!");
+                let journal = Arc::new(JournalDevice::new(
+                    block_device,
+                    super_block.total_blocks - super_block.journal_blocks,
+                    super_block.journal_blocks,
+                ));
+                journal.replay();
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
                 let efs = Self {
-                    block_device,
+                    block_device: journal.clone(),
                     inode_bitmap: Bitmap::new(
                         1,
                         super_block.inode_bitmap_blocks as usize
@@ -108,9 +223,32 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    data_area_blocks: super_block.data_area_blocks,
+                    long_names: super_block.long_names(),
+                    journal,
                 };
-                Arc::new(Mutex::new(efs))
-            })        
+                efs
+            });
+        if baseline {
+            efs.upgrade_baseline();
+        }
+        Arc::new(Mutex::new(efs))
+    }
+
+    //这个镜像上一个名字最多能有多长
+    pub fn name_length_limit(&self) -> usize {
+        if self.long_names { LONG_NAME_LENGTH_LIMIT } else { NAME_LENGTH_LIMIT }
+    }
+
+    //名字为name的目录项在磁盘上占多少字节，打包镜像的时候估算目录大小用
+    pub fn dirent_size(name: &str, long_names: bool) -> usize {
+        dirent_slots(name.len(), long_names) * DIRENT_SZ
+    }
+
+    //开一个事务，返回的守卫drop的时候提交
+    //事务可以嵌套，只有最外面那一层结束的时候才真正提交
+    pub fn transaction(&self) -> Transaction {
+        self.journal.begin()
     }
 
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
@@ -155,12 +293,34 @@ impl EasyFileSystem {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
 
-    /// Return a block ID not ID in the data area.
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    //inode的内容也清零，免得以后分配到它的时候读到旧数据
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+        get_block_cache(
+            block_id as usize,
+            Arc::clone(&self.block_device)
+        )
+        .lock()
+        .modify(block_offset, |disk_inode: &mut DiskInode| {
+            disk_inode.initialize(DiskInodeType::File);
+            disk_inode.nlink = 0;
+        });
+        self.inode_bitmap.dealloc(&self.block_device, inode_id as usize)
     }
 
-    pub fn dealloc_data(&mut self, block_id: u32) {
+    /// Return a block ID not ID in the data area.
+    //分配出来的块先清零，释放的时候就不用清了
+    //清零是直接写到块上的，不进日志，见journal.rs
+    //没有空闲块了返回None
+    pub fn alloc_data(&mut self) -> Option<u32> {
+        let bit = self.data_bitmap.alloc(&self.block_device)? as u32;
+        //位图的最后一块可能比数据区多出来几位，不能把日志区分出去
+        if bit >= self.data_area_blocks {
+            self.data_bitmap.dealloc(&self.block_device, bit as usize);
+            return None;
+        }
+        let block_id = bit + self.data_area_start_block;
+        self.journal.mark_allocated(block_id as usize);
         get_block_cache(
             block_id as usize,
             Arc::clone(&self.block_device)
@@ -169,6 +329,16 @@ impl EasyFileSystem {
         .modify(0, |data_block: &mut DataBlock| {
             data_block.iter_mut().for_each(|p| { *p = 0; })
         });
+        Some(block_id)
+    }
+
+    //数据区还剩多少空闲块
+    pub fn free_data_blocks(&self) -> usize {
+        self.data_area_blocks as usize - self.data_bitmap.count_allocated(&self.block_device)
+    }
+
+    pub fn dealloc_data(&mut self, block_id: u32) {
+        self.journal.mark_freed(block_id as usize);
         self.data_bitmap.dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize
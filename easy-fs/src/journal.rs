@@ -0,0 +1,242 @@
+use super::{
+    BLOCK_SZ,
+    BlockDevice,
+    block_cache_sync_all,
+};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+//元数据日志（redo log），放在磁盘最后journal_blocks个块里
+//第一个块是JournalHeader，后面依次是要写回去的块的新内容
+//
+//一个事务里改过的块先不写到原来的位置，而是攒在内存里，事务结束的时候：
+//  1. 把这些块的新内容写进日志区
+//  2. 写日志头，count不为0就表示这个事务提交了，这一步只写一个块，是原子的
+//  3. 把这些块写回原来的位置
+//  4. 日志头的count清零
+//在1、2之间崩溃，原来的位置一点都没动过，事务就当没发生过；
+//在2之后崩溃，下次open的时候照着日志再写一遍就行了
+//
+//事务开始的时候还是空闲、在事务里才分配出来的数据块不进日志，直接写到原来的位置：
+//事务没提交的话这些块在磁盘上还是空闲的，写了什么都无所谓
+//一个事务改的其他块不能超过日志的容量，一次要释放或者分配很多块的操作（截断、删除大文件）
+//要自己拆成几个事务，每个事务结束的时候文件系统都是完整的，见resize_step
+
+const JOURNAL_MAGIC: u32 = 0x4a524e4c;
+//日志头里面能记多少个块号
+const JOURNAL_HEADER_IDS: usize = BLOCK_SZ / 4 - 2;
+//新建镜像默认的日志大小：一个日志头加上它能记下的那么多块
+pub const DEFAULT_JOURNAL_BLOCKS: u32 = 1 + JOURNAL_HEADER_IDS as u32;
+//再小就连拆开之后的一步也放不下了
+pub const MIN_JOURNAL_BLOCKS: u32 = 1 + 2 * RESIZE_RESERVED_BLOCKS as u32;
+//拆开的每一步里除了位图块以外还会改到的块：inode、inode位图、
+//一级和二级索引块、最后一个二级索引下面的一级索引块、截断时清零的最后一个数据块，
+//再加上这一步里分配或者释放的索引块各自所在的位图块
+const RESIZE_RESERVED_BLOCKS: usize = 8;
+
+#[repr(C)]
+struct JournalHeader {
+    magic: u32,
+    count: u32,
+    blocks: [u32; JOURNAL_HEADER_IDS],
+}
+
+type DataBlock = [u8; BLOCK_SZ];
+
+struct JournalState {
+    //事务嵌套了几层，只有最外面那一层结束的时候才提交
+    depth: usize,
+    //事务里写过的块，块号 -> 新内容
+    pending: BTreeMap<usize, DataBlock>,
+    //事务开始的时候还空闲、事务里分配出来的块，写它们不用进日志
+    fresh: BTreeSet<usize>,
+    //事务里释放掉的块，事务提交之前它们在磁盘上还属于原来的文件，再分配出来也不能直接写
+    freed: BTreeSet<usize>,
+}
+
+//包在真正的块设备外面，EasyFileSystem和块缓存看到的都是它
+//不在事务里的时候直接透传
+pub struct JournalDevice {
+    inner: Arc<dyn BlockDevice>,
+    journal_start: usize,
+    journal_blocks: usize,
+    state: Mutex<JournalState>,
+}
+
+//事务的守卫，drop的时候事务结束
+pub struct Transaction {
+    journal: Arc<JournalDevice>,
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.journal.end();
+    }
+}
+
+impl JournalDevice {
+    //journal_blocks为0就是没有日志的老镜像，事务什么都不做
+    pub fn new(inner: Arc<dyn BlockDevice>, journal_start: u32, journal_blocks: u32) -> Self {
+        Self {
+            inner,
+            journal_start: journal_start as usize,
+            journal_blocks: journal_blocks as usize,
+            state: Mutex::new(JournalState {
+                depth: 0,
+                pending: BTreeMap::new(),
+                fresh: BTreeSet::new(),
+                freed: BTreeSet::new(),
+            }),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        (self.journal_blocks.saturating_sub(1)).min(JOURNAL_HEADER_IDS)
+    }
+
+    //截断、删除大文件的时候，一个事务里最多分配或者释放多少个数据块
+    //最坏情况下每个块都在不同的位图块里，这样事务也放得进日志；没有日志就不用拆
+    pub fn resize_step(&self) -> usize {
+        if self.capacity() == 0 {
+            usize::MAX
+        } else {
+            self.capacity().saturating_sub(RESIZE_RESERVED_BLOCKS).max(1)
+        }
+    }
+
+    //EasyFileSystem分配数据块的时候调用
+    pub fn mark_allocated(&self, block_id: usize) {
+        let mut state = self.state.lock();
+        if state.depth > 0 && !state.freed.contains(&block_id) {
+            state.fresh.insert(block_id);
+        }
+    }
+
+    //EasyFileSystem释放数据块的时候调用，本事务里刚分配的块释放了也还是空闲的
+    pub fn mark_freed(&self, block_id: usize) {
+        let mut state = self.state.lock();
+        if state.depth > 0 && !state.fresh.contains(&block_id) {
+            state.freed.insert(block_id);
+        }
+    }
+
+    pub fn begin(self: &Arc<Self>) -> Transaction {
+        if self.capacity() > 0 {
+            let outermost = self.state.lock().depth == 0;
+            if outermost {
+                //事务开始之前先把以前攒下的脏块直接写回去，事务里面就只剩下自己改的块
+                block_cache_sync_all();
+            }
+            self.state.lock().depth += 1;
+        }
+        Transaction { journal: Arc::clone(self) }
+    }
+
+    fn end(&self) {
+        if self.capacity() == 0 {
+            return;
+        }
+        if self.state.lock().depth > 1 {
+            self.state.lock().depth -= 1;
+            return;
+        }
+        //还在事务里，缓存里的脏块都会落到pending里面
+        block_cache_sync_all();
+        let pending = {
+            let mut state = self.state.lock();
+            state.depth = 0;
+            state.fresh.clear();
+            state.freed.clear();
+            core::mem::replace(&mut state.pending, BTreeMap::new())
+        };
+        //write_block保证了放得下，整个事务一次提交
+        let pending: Vec<(usize, DataBlock)> = pending.into_iter().collect();
+        if !pending.is_empty() {
+            self.commit(&pending);
+        }
+    }
+
+    fn commit(&self, blocks: &[(usize, DataBlock)]) {
+        let mut header = JournalHeader {
+            magic: JOURNAL_MAGIC,
+            count: 0,
+            blocks: [0; JOURNAL_HEADER_IDS],
+        };
+        for (i, (block_id, data)) in blocks.iter().enumerate() {
+            self.inner.write_block(self.journal_start + 1 + i, data);
+            header.blocks[i] = *block_id as u32;
+        }
+        header.count = blocks.len() as u32;
+        self.write_header(&header);
+        for (block_id, data) in blocks.iter() {
+            self.inner.write_block(*block_id, data);
+        }
+        header.count = 0;
+        self.write_header(&header);
+    }
+
+    fn write_header(&self, header: &JournalHeader) {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(header as *const _ as *const u8, BLOCK_SZ)
+        };
+        self.inner.write_block(self.journal_start, bytes);
+    }
+
+    //open的时候调用：日志里有提交了但是还没写完的事务就再写一遍
+    //返回重放了多少个块
+    pub fn replay(&self) -> usize {
+        if self.capacity() == 0 {
+            return 0;
+        }
+        let mut header = JournalHeader {
+            magic: 0,
+            count: 0,
+            blocks: [0; JOURNAL_HEADER_IDS],
+        };
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(&mut header as *mut _ as *mut u8, BLOCK_SZ)
+        };
+        self.inner.read_block(self.journal_start, bytes);
+        let count = header.count as usize;
+        if header.magic != JOURNAL_MAGIC || count == 0 || count > self.capacity() {
+            return 0;
+        }
+        let mut data = [0u8; BLOCK_SZ];
+        for i in 0..count {
+            self.inner.read_block(self.journal_start + 1 + i, &mut data);
+            self.inner.write_block(header.blocks[i] as usize, &data);
+        }
+        header.count = 0;
+        self.write_header(&header);
+        count
+    }
+}
+
+impl BlockDevice for JournalDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        if let Some(data) = self.state.lock().pending.get(&block_id) {
+            buf.copy_from_slice(data);
+            return;
+        }
+        self.inner.read_block(block_id, buf);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut state = self.state.lock();
+        if state.depth > 0 && !state.fresh.contains(&block_id) {
+            //分几个事务提交就不是原子的了，放不下说明调用者没有把操作拆开
+            assert!(
+                state.pending.len() < self.capacity() || state.pending.contains_key(&block_id),
+                "easy-fs: transaction does not fit in the journal"
+            );
+            let mut data = [0u8; BLOCK_SZ];
+            data.copy_from_slice(buf);
+            state.pending.insert(block_id, data);
+        } else {
+            drop(state);
+            self.inner.write_block(block_id, buf);
+        }
+    }
+}
@@ -3,19 +3,42 @@ use super::{
     BLOCK_SZ,
     BlockDevice,
     get_block_cache,
+    now_ms,
 };
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use alloc::vec;
+use alloc::string::String;
 
-const EFS_MAGIC: u32 = 0x3b800001;
-const INODE_DIRECT_COUNT: usize = 28;
-const NAME_LENGTH_LIMIT: usize = 27;
+//DiskInode的布局变了魔数就跟着换掉，老镜像直接认不出来，免得被读错
+//0x3b800002加了nlink，直接索引从28个变成27个；0x3b800003加了时间戳，直接索引变成21个
+//0x3b800004加了权限和属主，直接索引变成19个
+const EFS_MAGIC: u32 = 0x3b800004;
+//以前用过的魔数，这些镜像的DiskInode和现在对不上
+const BASELINE_EFS_MAGIC: u32 = 0x3b800001;
+const OLD_EFS_MAGICS: [u32; 3] = [BASELINE_EFS_MAGIC, 0x3b800002, 0x3b800003];
+//超级块里的版本号，没有这个字段的老镜像读出来是0
+//从EFS_VERSION_LONG_NAME开始目录项支持长名字
+//版本号只管目录项的格式，inode的布局由魔数决定：
+//最早的打包工具做出来的镜像打开的时候会先转换成现在的布局（见upgrade.rs），其他老布局不认
+pub const EFS_VERSION_LONG_NAME: u32 = 2;
+const INODE_DIRECT_COUNT: usize = 19;
+pub const NAME_LENGTH_LIMIT: usize = 27;
+pub const LONG_NAME_LENGTH_LIMIT: usize = 255;
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+//一个文件最大能有多少字节
+pub const MAX_FILE_SIZE: usize = INDIRECT2_BOUND * BLOCK_SZ;
+//新建的inode默认的权限位，和umask为022的时候一样
+pub const DEFAULT_FILE_MODE: u16 = 0o644;
+pub const DEFAULT_DIR_MODE: u16 = 0o755;
+pub const SYMLINK_MODE: u16 = 0o777;
+pub const ROOT_DIR_MODE: u16 = 0o1777;
+//权限位最多到这里：setuid、setgid、sticky和rwxrwxrwx
+pub const MODE_MASK: u16 = 0o7777;
 
 #[repr(C)]
 pub struct SuperBlock {
@@ -25,6 +48,9 @@ pub struct SuperBlock {
     pub inode_area_blocks: u32,
     pub data_bitmap_blocks: u32,
     pub data_area_blocks: u32,
+    pub version: u32,
+    //磁盘最后的这么多块是元数据日志，老镜像读出来是0，就是没有日志
+    pub journal_blocks: u32,
 }
 
 impl Debug for SuperBlock {
@@ -35,6 +61,8 @@ impl Debug for SuperBlock {
             .field("inode_area_blocks", &self.inode_area_blocks)
             .field("data_bitmap_blocks", &self.data_bitmap_blocks)
             .field("data_area_blocks", &self.data_area_blocks)
+            .field("version", &self.version)
+            .field("journal_blocks", &self.journal_blocks)
             .finish()
     }
 }
@@ -47,6 +75,8 @@ impl SuperBlock {
         inode_area_blocks: u32,
         data_bitmap_blocks: u32,
         data_area_blocks: u32,
+        version: u32,
+        journal_blocks: u32,
     ) {
         *self = Self {
             magic: EFS_MAGIC,
@@ -55,17 +85,36 @@ impl SuperBlock {
             inode_area_blocks,
             data_bitmap_blocks,
             data_area_blocks,
+            version,
+            journal_blocks,
         }
     }
     pub fn is_valid(&self) -> bool {
         self.magic == EFS_MAGIC
     }
+    //是不是老布局的镜像，这种镜像不能直接按现在的DiskInode去读
+    pub fn is_old_layout(&self) -> bool {
+        OLD_EFS_MAGICS.contains(&self.magic)
+    }
+    //最早的镜像超级块里只有前面几个字段，后面读出来都是0
+    pub fn is_baseline(&self) -> bool {
+        self.magic == BASELINE_EFS_MAGIC && self.version == 0 && self.journal_blocks == 0
+    }
+    //转换完老镜像的inode之后调用
+    pub fn upgrade_magic(&mut self) {
+        self.magic = EFS_MAGIC;
+    }
+    pub fn long_names(&self) -> bool {
+        self.version >= EFS_VERSION_LONG_NAME
+    }
 }
 
 #[derive(PartialEq)]
 pub enum DiskInodeType {
     File,
     Directory,
+    //内容就是它指向的路径
+    Symlink,
 }
 
 type IndirectBlock = [u32; BLOCK_SZ / 4];
@@ -79,9 +128,22 @@ pub struct DiskInode {
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    //有多少个目录项指向这个inode，减到0就可以回收了
+    pub nlink: u32,
     type_: DiskInodeType,
+    //只有权限位，类型在type_里；属主默认是root
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    //最后一次读、写内容、改inode本身（包括写内容）的时间，毫秒，见set_clock
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
 }
 
+//大小不对的话这里编译不过
+const _: [(); 128] = [(); core::mem::size_of::<DiskInode>()];
+
 //DiskInode的功能大概就类似一个目录（？）
 //反正似乎是把inode-id输入，就可以给出blockid的输出
 //然后拿着这个blockid操作磁盘就可以了
@@ -94,7 +156,32 @@ impl DiskInode {
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        //刚建出来的时候只有父目录里的一个目录项指向它
+        self.nlink = 1;
+        self.mode = match type_ {
+            DiskInodeType::File => DEFAULT_FILE_MODE,
+            DiskInodeType::Directory => DEFAULT_DIR_MODE,
+            DiskInodeType::Symlink => SYMLINK_MODE,
+        };
+        self.uid = 0;
+        self.gid = 0;
         self.type_ = type_;
+        let now = now_ms();
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+    }
+    pub fn touch_atime(&mut self) {
+        self.atime = now_ms();
+    }
+    //内容变了，inode也跟着变了
+    pub fn touch_mtime(&mut self) {
+        self.mtime = now_ms();
+        self.ctime = self.mtime;
+    }
+    //只改了inode，比如nlink
+    pub fn touch_ctime(&mut self) {
+        self.ctime = now_ms();
     }
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
@@ -103,6 +190,9 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::Symlink
+    }
     /// Return block number correspond to size.
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -126,6 +216,22 @@ impl DiskInode {
         }
         total as u32
     }
+    //把数据块和索引块排成increase_size从空文件开始取块的顺序
+    pub fn interleave_blocks(data: &[u32], index: Vec<u32>) -> Vec<u32> {
+        let mut index = index.into_iter();
+        let mut v: Vec<u32> = Vec::new();
+        for (inner_id, &block_id) in data.iter().enumerate() {
+            // indirect1, indirect2, then a low-level indirect1 every INODE_INDIRECT1_COUNT blocks
+            if inner_id == DIRECT_BOUND || inner_id == INDIRECT1_BOUND {
+                v.push(index.next().unwrap());
+            }
+            if inner_id >= INDIRECT1_BOUND && (inner_id - INDIRECT1_BOUND) % INODE_INDIRECT1_COUNT == 0 {
+                v.push(index.next().unwrap());
+            }
+            v.push(block_id);
+        }
+        v
+    }
     pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
         assert!(new_size >= self.size);
         Self::total_blocks(new_size) - Self::total_blocks(self.size)
@@ -166,6 +272,72 @@ impl DiskInode {
             })
         }
     }
+    //不修改inode，列出它占用的所有块（包括索引块），fsck用
+    //碰到in_range之外的块号说明inode已经坏了，返回None
+    pub fn all_blocks(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        in_range: impl Fn(u32) -> bool,
+    ) -> Option<Vec<u32>> {
+        let mut data_blocks = self.data_blocks() as usize;
+        if data_blocks > INDIRECT2_BOUND {
+            return None;
+        }
+        let mut v: Vec<u32> = Vec::new();
+        // direct
+        v.extend_from_slice(&self.direct[..data_blocks.min(INODE_DIRECT_COUNT)]);
+        // indirect1
+        if data_blocks > INODE_DIRECT_COUNT {
+            if !in_range(self.indirect1) {
+                return None;
+            }
+            v.push(self.indirect1);
+            data_blocks -= INODE_DIRECT_COUNT;
+            get_block_cache(
+                self.indirect1 as usize,
+                Arc::clone(block_device)
+            )
+            .lock()
+            .read(0, |indirect1: &IndirectBlock| {
+                v.extend_from_slice(&indirect1[..data_blocks.min(INODE_INDIRECT1_COUNT)]);
+            });
+        }
+        // indirect2
+        if data_blocks > INODE_INDIRECT1_COUNT {
+            if !in_range(self.indirect2) {
+                return None;
+            }
+            v.push(self.indirect2);
+            data_blocks -= INODE_INDIRECT1_COUNT;
+            let indirect1_count = (data_blocks + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+            let indirect1_blocks: Vec<u32> = get_block_cache(
+                self.indirect2 as usize,
+                Arc::clone(block_device)
+            )
+            .lock()
+            .read(0, |indirect2: &IndirectBlock| indirect2[..indirect1_count].to_vec());
+            for (i, indirect1) in indirect1_blocks.into_iter().enumerate() {
+                if !in_range(indirect1) {
+                    return None;
+                }
+                v.push(indirect1);
+                let count = (data_blocks - i * INODE_INDIRECT1_COUNT).min(INODE_INDIRECT1_COUNT);
+                get_block_cache(
+                    indirect1 as usize,
+                    Arc::clone(block_device)
+                )
+                .lock()
+                .read(0, |indirect1: &IndirectBlock| {
+                    v.extend_from_slice(&indirect1[..count]);
+                });
+            }
+        }
+        if v.iter().all(|&block_id| in_range(block_id)) {
+            Some(v)
+        } else {
+            None
+        }
+    }
     pub fn increase_size(
         &mut self,
         new_size: u32,
@@ -247,6 +419,61 @@ impl DiskInode {
             } 
         });
     }
+
+    /// Shrink size to new_size and return blocks that should be deallocated,
+    /// including indirect blocks that are no longer needed.
+    //最后一个留下来的块里面new_size后面的部分清零，以后再变大的时候读到的是0
+    pub fn decrease_size(&mut self, new_size: u32, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let old_blocks = self.data_blocks() as usize;
+        let new_blocks = Self::_data_blocks(new_size) as usize;
+        let tail = new_size as usize % BLOCK_SZ;
+        if tail != 0 && new_size < self.size {
+            get_block_cache(
+                self.get_block_id(new_blocks as u32 - 1, block_device) as usize,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .modify(0, |data_block: &mut DataBlock| {
+                data_block[tail..].iter_mut().for_each(|p| *p = 0);
+            });
+        }
+        let mut v: Vec<u32> = (new_blocks..old_blocks)
+            .map(|inner_id| self.get_block_id(inner_id as u32, block_device))
+            .collect();
+        for inner_id in new_blocks..old_blocks.min(INODE_DIRECT_COUNT) {
+            self.direct[inner_id] = 0;
+        }
+        // indirect1 block
+        if new_blocks <= INODE_DIRECT_COUNT && old_blocks > INODE_DIRECT_COUNT {
+            v.push(self.indirect1);
+            self.indirect1 = 0;
+        }
+        // low-level indirect1 blocks under indirect2, then indirect2 itself
+        let old2 = old_blocks.saturating_sub(INDIRECT1_BOUND);
+        let new2 = new_blocks.saturating_sub(INDIRECT1_BOUND);
+        if old2 > 0 {
+            let old_count = (old2 + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+            let new_count = (new2 + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+            get_block_cache(
+                self.indirect2 as usize,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .modify(0, |indirect2: &mut IndirectBlock| {
+                for i in new_count..old_count {
+                    v.push(indirect2[i]);
+                    indirect2[i] = 0;
+                }
+            });
+            if new2 == 0 {
+                v.push(self.indirect2);
+                self.indirect2 = 0;
+            }
+        }
+        self.size = new_size;
+        v
+    }
     
     /*
 
@@ -571,4 +798,94 @@ impl DirEntry {
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
-}
\ No newline at end of file
+}
+//长名字格式（EFS_VERSION_LONG_NAME）的目录项：
+//一个目录项占若干个连续的32字节槽位，第一个槽位是LongDirEntryHead，
+//名字的前24个字节放在head里面，剩下的依次放在后面的槽位里
+//被删掉的目录项name_len为0，但是slots不变，这样后面的目录项还能找到
+#[repr(C)]
+struct LongDirEntryHead {
+    name_len: u8,
+    slots: u8,
+    _pad: u16,
+    inode_number: u32,
+    name: [u8; LONG_NAME_HEAD],
+}
+
+const LONG_NAME_HEAD: usize = DIRENT_SZ - 8;
+
+//从目录内容里解析出来的一个目录项，被删掉的目录项name为空
+//slot和slots是它占的32字节槽位的位置和个数，老格式里slots总是1
+pub struct DirSlot {
+    pub slot: usize,
+    pub slots: usize,
+    pub name: String,
+    pub inode_number: u32,
+}
+
+//名字为name_len字节的目录项要占几个槽位
+pub fn dirent_slots(name_len: usize, long_names: bool) -> usize {
+    if !long_names || name_len <= LONG_NAME_HEAD {
+        1
+    } else {
+        1 + (name_len - LONG_NAME_HEAD + DIRENT_SZ - 1) / DIRENT_SZ
+    }
+}
+
+//把一整个目录的内容解析成目录项
+pub fn parse_dirents(data: &[u8], long_names: bool) -> Vec<DirSlot> {
+    let total_slots = data.len() / DIRENT_SZ;
+    let mut v: Vec<DirSlot> = Vec::new();
+    let mut slot = 0usize;
+    while slot < total_slots {
+        let bytes = &data[slot * DIRENT_SZ..];
+        if !long_names {
+            let mut dirent = DirEntry::empty();
+            dirent.as_bytes_mut().copy_from_slice(&bytes[..DIRENT_SZ]);
+            v.push(DirSlot {
+                slot,
+                slots: 1,
+                name: String::from(dirent.name()),
+                inode_number: dirent.inode_number(),
+            });
+            slot += 1;
+            continue;
+        }
+        let head = unsafe { &*(bytes.as_ptr() as *const LongDirEntryHead) };
+        //坏掉的目录项也至少跳过一个槽位，免得死循环
+        let slots = (head.slots as usize).max(1).min(total_slots - slot);
+        let name_len = (head.name_len as usize).min(slots * DIRENT_SZ - 8);
+        let mut name_bytes: Vec<u8> = Vec::new();
+        name_bytes.extend_from_slice(&head.name[..name_len.min(LONG_NAME_HEAD)]);
+        if name_len > LONG_NAME_HEAD {
+            name_bytes.extend_from_slice(&bytes[DIRENT_SZ..DIRENT_SZ + name_len - LONG_NAME_HEAD]);
+        }
+        v.push(DirSlot {
+            slot,
+            slots,
+            name: String::from_utf8_lossy(&name_bytes).into_owned(),
+            inode_number: head.inode_number,
+        });
+        slot += slots;
+    }
+    v
+}
+
+//编码一个占slots个槽位的目录项，name为空就是空目录项
+pub fn encode_dirent(name: &str, inode_number: u32, slots: usize, long_names: bool) -> Vec<u8> {
+    if !long_names {
+        return DirEntry::new(name, inode_number).as_bytes().to_vec();
+    }
+    let mut bytes = vec![0u8; slots * DIRENT_SZ];
+    bytes[0] = name.len() as u8;
+    bytes[1] = slots as u8;
+    bytes[4..8].copy_from_slice(&inode_number.to_ne_bytes());
+    let name_len = name.len();
+    bytes[8..8 + name_len.min(LONG_NAME_HEAD)]
+        .copy_from_slice(&name.as_bytes()[..name_len.min(LONG_NAME_HEAD)]);
+    if name_len > LONG_NAME_HEAD {
+        bytes[DIRENT_SZ..DIRENT_SZ + name_len - LONG_NAME_HEAD]
+            .copy_from_slice(&name.as_bytes()[LONG_NAME_HEAD..]);
+    }
+    bytes
+}
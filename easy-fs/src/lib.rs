@@ -13,11 +13,21 @@ mod efs;
 mod bitmap;
 mod vfs;
 mod block_cache;
+mod check;
+mod journal;
+mod upgrade;
 
 pub const BLOCK_SZ: usize = 512;
-pub use block_dev::BlockDevice;
+pub use layout::{NAME_LENGTH_LIMIT, LONG_NAME_LENGTH_LIMIT, EFS_VERSION_LONG_NAME, MAX_FILE_SIZE};
+pub use layout::{DEFAULT_FILE_MODE, DEFAULT_DIR_MODE, SYMLINK_MODE, MODE_MASK};
+pub use block_dev::{BlockDevice, set_clock};
+use block_dev::now_ms;
 pub use efs::EasyFileSystem;
-pub use vfs::Inode;
+pub use vfs::{Inode, DirEntryInfo};
+pub use check::CheckReport;
 use layout::*;
 use bitmap::Bitmap;
-use block_cache::get_block_cache;
\ No newline at end of file
+use journal::JournalDevice;
+pub use journal::{Transaction, DEFAULT_JOURNAL_BLOCKS, MIN_JOURNAL_BLOCKS};
+use block_cache::get_block_cache;
+pub use block_cache::{block_cache_sync_all, set_block_cache_capacity, block_cache_len};
\ No newline at end of file
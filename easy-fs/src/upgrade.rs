@@ -0,0 +1,245 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use super::{
+    BlockDevice,
+    EasyFileSystem,
+    SuperBlock,
+    DiskInode,
+    DiskInodeType,
+    DIRENT_SZ,
+    ROOT_DIR_MODE,
+    parse_dirents,
+    get_block_cache,
+    block_cache_sync_all,
+};
+use crate::BLOCK_SZ;
+
+//最早的打包工具做出来的镜像里的DiskInode：28个直接索引，没有nlink、时间和属主
+//type_是0（文件）或者1（目录），也是128个字节
+const BASELINE_DIRECT_COUNT: usize = 28;
+const INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
+const BASELINE_INDIRECT1_BOUND: usize = BASELINE_DIRECT_COUNT + INDIRECT1_COUNT;
+
+#[repr(C)]
+struct BaselineDiskInode {
+    size: u32,
+    direct: [u32; BASELINE_DIRECT_COUNT],
+    indirect1: u32,
+    indirect2: u32,
+    type_: u8,
+}
+
+const _: [(); 128] = [(); core::mem::size_of::<BaselineDiskInode>()];
+
+type IndirectBlock = [u32; BLOCK_SZ / 4];
+type DataBlock = [u8; BLOCK_SZ];
+
+//老inode转换之前先整理出来的样子
+struct BaselineInode {
+    inode_id: u32,
+    is_dir: bool,
+    size: u32,
+    //按文件里的顺序排好的数据块
+    data: Vec<u32>,
+    //原来的索引块，转换的时候拿来重用
+    index: Vec<u32>,
+}
+
+fn read_baseline_inode<V>(
+    block_id: u32,
+    offset: usize,
+    block_device: &Arc<dyn BlockDevice>,
+    f: impl FnOnce(&BaselineDiskInode) -> V,
+) -> V {
+    get_block_cache(block_id as usize, Arc::clone(block_device))
+        .lock()
+        .read(offset, f)
+}
+
+fn read_indirect(block_id: u32, block_device: &Arc<dyn BlockDevice>) -> IndirectBlock {
+    get_block_cache(block_id as usize, Arc::clone(block_device))
+        .lock()
+        .read(0, |indirect: &IndirectBlock| *indirect)
+}
+
+//按老布局把一个inode的数据块和索引块都找出来
+fn baseline_blocks(
+    disk_inode: &BaselineDiskInode,
+    block_device: &Arc<dyn BlockDevice>,
+) -> (Vec<u32>, Vec<u32>) {
+    let count = (disk_inode.size as usize + BLOCK_SZ - 1) / BLOCK_SZ;
+    let mut data: Vec<u32> = Vec::new();
+    let mut index: Vec<u32> = Vec::new();
+    data.extend_from_slice(&disk_inode.direct[..count.min(BASELINE_DIRECT_COUNT)]);
+    if count > BASELINE_DIRECT_COUNT {
+        index.push(disk_inode.indirect1);
+        let indirect1 = read_indirect(disk_inode.indirect1, block_device);
+        data.extend_from_slice(&indirect1[..(count - BASELINE_DIRECT_COUNT).min(INDIRECT1_COUNT)]);
+    }
+    if count > BASELINE_INDIRECT1_BOUND {
+        index.push(disk_inode.indirect2);
+        let indirect2 = read_indirect(disk_inode.indirect2, block_device);
+        let rest = count - BASELINE_INDIRECT1_BOUND;
+        for i in 0..(rest + INDIRECT1_COUNT - 1) / INDIRECT1_COUNT {
+            index.push(indirect2[i]);
+            let indirect1 = read_indirect(indirect2[i], block_device);
+            data.extend_from_slice(&indirect1[..(rest - i * INDIRECT1_COUNT).min(INDIRECT1_COUNT)]);
+        }
+    }
+    (data, index)
+}
+
+impl EasyFileSystem {
+    //是不是最早的打包工具做出来的镜像：老魔数，超级块里没有版本号和日志，
+    //根目录下也没有"."（后来用同一个魔数的开发版镜像根目录第一项就是"."，那些不认）
+    pub fn is_baseline_image(block_device: &Arc<dyn BlockDevice>) -> bool {
+        let root = get_block_cache(0, Arc::clone(block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                if super_block.is_baseline() {
+                    Some(1 + super_block.inode_bitmap_blocks)
+                } else {
+                    None
+                }
+            });
+        let root_block = match root {
+            Some(block_id) => block_id,
+            None => return false,
+        };
+        let (is_dir, size, first_block) = read_baseline_inode(
+            root_block,
+            0,
+            block_device,
+            |root: &BaselineDiskInode| (root.type_ == 1, root.size, root.direct[0]),
+        );
+        if !is_dir {
+            return false;
+        }
+        if size as usize >= DIRENT_SZ {
+            let first = get_block_cache(first_block as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |data_block: &DataBlock| parse_dirents(&data_block[..DIRENT_SZ], false));
+            if first[0].name == "." {
+                return false;
+            }
+        }
+        true
+    }
+
+    //把最早的镜像原地转换成现在的布局，镜像的几何参数和目录项格式（version 0）都不变：
+    //  按老布局读出每个inode的数据块，再按现在的布局重新建索引，
+    //  原来的索引块优先重用，不够再分配，多出来的释放掉；
+    //  nlink按照指向它的目录项数来算，权限和属主用默认值，没有目录项指向的inode回收掉
+    //最后才把超级块的魔数改掉。转换不是原子的，中途断电镜像就坏了，重要的镜像先备份
+    pub(crate) fn upgrade_baseline(&mut self) {
+        let block_device = Arc::clone(&self.block_device);
+        let data_start = self.data_area_start_block;
+        let data_end = data_start + self.data_area_blocks;
+        let in_range = |block_id: u32| block_id >= data_start && block_id < data_end;
+        let inode_count = self.inode_bitmap.maximum();
+
+        //先把所有inode都按老布局读出来，后面改写的时候老的索引块就可以随便用了
+        let mut inodes: Vec<BaselineInode> = Vec::new();
+        for inode_id in 0..inode_count as u32 {
+            if !self.inode_bitmap.test(&block_device, inode_id as usize) {
+                continue;
+            }
+            let (block_id, offset) = self.get_disk_inode_pos(inode_id);
+            let (is_dir, size, (data, index)) = read_baseline_inode(
+                block_id,
+                offset,
+                &block_device,
+                |disk_inode: &BaselineDiskInode| {
+                    (disk_inode.type_ == 1, disk_inode.size, baseline_blocks(disk_inode, &block_device))
+                },
+            );
+            assert!(
+                data.iter().chain(index.iter()).all(|&block_id| in_range(block_id)),
+                "easy-fs: baseline inode {} points outside the data area",
+                inode_id
+            );
+            inodes.push(BaselineInode { inode_id, is_dir, size, data, index });
+        }
+
+        //links[id]：有多少个目录项指向它，根目录算一个
+        let mut links = vec![0u32; inode_count];
+        links[0] = 1;
+        for inode in inodes.iter().filter(|inode| inode.is_dir) {
+            let mut content: Vec<u8> = Vec::new();
+            for &block_id in inode.data.iter() {
+                get_block_cache(block_id as usize, Arc::clone(&block_device))
+                    .lock()
+                    .read(0, |data_block: &DataBlock| content.extend_from_slice(data_block));
+            }
+            content.truncate(inode.size as usize / DIRENT_SZ * DIRENT_SZ);
+            for dirent in parse_dirents(&content, false) {
+                if !dirent.name.is_empty() && (dirent.inode_number as usize) < inode_count {
+                    links[dirent.inode_number as usize] += 1;
+                }
+            }
+        }
+
+        //老版本的delete_linker只清空目录项，不回收inode，这些没人指向的inode直接释放掉
+        let (inodes, orphans): (Vec<BaselineInode>, Vec<BaselineInode>) = inodes
+            .into_iter()
+            .partition(|inode| links[inode.inode_id as usize] > 0);
+        for orphan in orphans {
+            for &block_id in orphan.data.iter().chain(orphan.index.iter()) {
+                self.dealloc_data(block_id);
+            }
+            self.dealloc_inode(orphan.inode_id);
+        }
+
+        //新布局需要的索引块个数和原来不一样，先确认空闲块够用再动手
+        let mut pool: Vec<u32> = inodes.iter().flat_map(|inode| inode.index.iter().copied()).collect();
+        let needed: usize = inodes
+            .iter()
+            .map(|inode| (DiskInode::total_blocks(inode.size) as usize) - inode.data.len())
+            .sum();
+        if needed > pool.len() {
+            assert!(
+                self.free_data_blocks() >= needed - pool.len(),
+                "easy-fs: not enough free blocks to upgrade the baseline image"
+            );
+        }
+
+        for inode in inodes.iter() {
+            let index_count = DiskInode::total_blocks(inode.size) as usize - inode.data.len();
+            let mut index: Vec<u32> = Vec::new();
+            for _ in 0..index_count {
+                let block_id = pool.pop().or_else(|| self.alloc_data()).unwrap();
+                get_block_cache(block_id as usize, Arc::clone(&block_device))
+                    .lock()
+                    .modify(0, |data_block: &mut DataBlock| data_block.iter_mut().for_each(|p| *p = 0));
+                index.push(block_id);
+            }
+            let new_blocks = DiskInode::interleave_blocks(&inode.data, index);
+            let (block_id, offset) = self.get_disk_inode_pos(inode.inode_id);
+            get_block_cache(block_id as usize, Arc::clone(&block_device))
+                .lock()
+                .modify(offset, |disk_inode: &mut DiskInode| {
+                    disk_inode.initialize(if inode.is_dir {
+                        DiskInodeType::Directory
+                    } else {
+                        DiskInodeType::File
+                    });
+                    disk_inode.nlink = links[inode.inode_id as usize];
+                    //老镜像都是root的，根目录和新建的时候一样所有人可写
+                    if inode.inode_id == 0 {
+                        disk_inode.mode = ROOT_DIR_MODE;
+                    }
+                    disk_inode.increase_size(inode.size, new_blocks, &block_device);
+                });
+        }
+        for block_id in pool {
+            self.dealloc_data(block_id);
+        }
+
+        block_cache_sync_all();
+        get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .modify(0, |super_block: &mut SuperBlock| super_block.upgrade_magic());
+        block_cache_sync_all();
+    }
+}
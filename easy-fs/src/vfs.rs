@@ -3,17 +3,42 @@ use super::{
     BlockDevice,
     DiskInode,
     DiskInodeType,
-    DirEntry,
+    DirSlot,
     EasyFileSystem,
+    JournalDevice,
+    Transaction,
     DIRENT_SZ,
+    MAX_FILE_SIZE,
+    DEFAULT_FILE_MODE,
+    DEFAULT_DIR_MODE,
+    SYMLINK_MODE,
+    MODE_MASK,
+    dirent_slots,
+    encode_dirent,
+    parse_dirents,
     get_block_cache,
+    BLOCK_SZ,
 };
 use alloc::sync::Arc;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 // use super::lib::*;
 
+//路径里最多跟多少次符号链接，再多就当成是绕成圈了，和Linux一样
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+//read_dir_from列出来的一个目录项
+pub struct DirEntryInfo {
+    pub name: String,
+    pub inode_id: u32,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    //下一个目录项在目录里的偏移（字节）
+    pub next: usize,
+}
+
 //注意，这个数据结构是放在内存里面的
 //某种意义上说可以随便修改······
 pub struct Inode {
@@ -24,6 +49,9 @@ pub struct Inode {
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
     block_device: Arc<dyn BlockDevice>,
+    //目录项的格式和日志，从EasyFileSystem里抄过来，免得每次都要拿锁
+    long_names: bool,
+    journal: Arc<JournalDevice>,
 }
 
 impl Inode {
@@ -33,16 +61,42 @@ impl Inode {
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
-        let (block_id, block_offset) = fs.lock().get_disk_inode_pos(inode_id);
+        let (block_id, block_offset, long_names, journal) = {
+            let fs = fs.lock();
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+            (block_id, block_offset, fs.long_names, Arc::clone(&fs.journal))
+        };
         Self {
             my_inode_id: inode_id,//new
             block_id: block_id as usize,
             block_offset,
             fs,
             block_device,
+            long_names,
+            journal,
+        }
+    }
+
+    //已经拿着fs的锁的时候用这个，fs和块设备从同一个文件系统里的inode那里抄过来
+    fn new_locked(inode_id: u32, fs: &EasyFileSystem, other: &Inode) -> Self {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        Self {
+            my_inode_id: inode_id,
+            block_id: block_id as usize,
+            block_offset,
+            fs: other.fs.clone(),
+            block_device: other.block_device.clone(),
+            long_names: fs.long_names,
+            journal: Arc::clone(&fs.journal),
         }
     }
 
+    //改元数据的操作都要包在事务里，一定要在拿fs的锁之前开
+    //嵌套调用别的方法的时候里面的事务会并到外面这个里
+    fn transaction(&self) -> Transaction {
+        self.journal.begin()
+    }
+
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(
             self.block_id,
@@ -57,6 +111,13 @@ impl Inode {
         ).lock().modify(self.block_offset, f)
     }
 
+    //把整个目录读出来解析成目录项，被删掉的目录项也在里面，name为空
+    fn read_dirents(&self, disk_inode: &DiskInode) -> Vec<DirSlot> {
+        let mut data = vec![0u8; disk_inode.size as usize];
+        disk_inode.read_at(0, &mut data, &self.block_device);
+        parse_dirents(&data, self.long_names)
+    }
+
     /*
     fn get_disk_inode(&self, fs: &mut MutexGuard<EasyFileSystem>) -> Dirty<DiskInode> {
         fs.get_disk_inode(self.inode_id)
@@ -80,20 +141,14 @@ impl Inode {
         // assert it is a directory
         fs_println!("find_inode_id::disk_node is dir...{}",disk_inode.is_dir());
         assert!(disk_inode.is_dir());
-        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(
-                    DIRENT_SZ * i,
-                    dirent.as_bytes_mut(),
-                    &self.block_device,
-                ),
-                DIRENT_SZ,
-            );
-            fs_println!("find_inode_id::dirent name is {}, inode number is {}",dirent.name(),dirent.inode_number());
-            if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
+        //空名字是被删掉的目录项，不能拿来匹配
+        if name.is_empty() {
+            return None;
+        }
+        for dirent in self.read_dirents(disk_inode) {
+            fs_println!("find_inode_id::dirent name is {}, inode number is {}",dirent.name,dirent.inode_number);
+            if dirent.name == name {
+                return Some(dirent.inode_number);
             }
         }
         None
@@ -117,22 +172,12 @@ impl Inode {
         let _ = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
             fs_println!("Inode::get_my_data::disk_node is dir...{}",disk_inode.is_dir());
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut dirent = DirEntry::empty();
-            for i in 0..file_count {
-                assert_eq!(
-                    disk_inode.read_at(
-                        DIRENT_SZ * i,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
+            for dirent in self.read_dirents(disk_inode) {
                 //TODO这里要修改输出，能够返回文件名鸭
-                // fs_println!("get_my_data::dirent name is {}, inode number is {}",dirent.name(),dirent.inode_number());
-                if dirent.inode_number() == self.my_inode_id {
-                    // return Some((dirent.inode_number() as u32, dirent.name()));
-                    fs_println!("get_my_data::dirent name is {}, inode number is {},but return None",dirent.name(),dirent.inode_number());
+                // fs_println!("get_my_data::dirent name is {}, inode number is {}",dirent.name,dirent.inode_number);
+                if dirent.inode_number == self.my_inode_id {
+                    // return Some((dirent.inode_number, dirent.name));
+                    fs_println!("get_my_data::dirent name is {}, inode number is {},but return None",dirent.name,dirent.inode_number);
                     return None;
                 }
             }
@@ -140,10 +185,19 @@ impl Inode {
         })
     }
 
+    pub fn is_dir(&self) -> bool {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
     //刚刚意识到哪里有问题！其实存inode编号就可以了？
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
         let _ = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
+            //文件下面不可能再找到别的文件
+            if !disk_inode.is_dir() {
+                return None;
+            }
             self.find_inode_id(name, disk_inode)
             .map(|inode_id| {
                 Arc::new(Self::new(
@@ -155,31 +209,137 @@ impl Inode {
         })
     }
 
+    //沿着路径一级一级往下找，路径是相对于自己的
+    //"."和".."本身就是目录项，所以不需要特殊处理
+    //碰到符号链接（包括最后一级）就换成它指向的路径，从链接所在的目录接着找，
+    //指向的路径以/开头的话从根目录开始
+    pub fn find_path(&self, path: &str) -> Option<Arc<Inode>> {
+        let mut current = Arc::new(Self::new(
+            self.my_inode_id,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ));
+        //还没走的部分倒着放，pop出来的就是下一级
+        let mut rest: Vec<String> = Self::path_components(path);
+        let mut follows = 0;
+        while let Some(name) = rest.pop() {
+            let next = match current.find(&name) {
+                Some(inode) => inode,
+                // the root of an old image has no ".." entry
+                None if name == ".." && current.my_inode_id == 0 => current.clone(),
+                None => return None,
+            };
+            if next.is_symlink() {
+                follows += 1;
+                if follows > MAX_SYMLINK_FOLLOWS {
+                    return None;
+                }
+                let target = next.read_link()?;
+                if target.starts_with('/') {
+                    current = Arc::new(Self::new(0, self.fs.clone(), self.block_device.clone()));
+                }
+                rest.extend(Self::path_components(&target));
+                continue;
+            }
+            current = next;
+        }
+        Some(current)
+    }
+
+    //倒序的路径分量，空的和.都去掉
+    fn path_components(path: &str) -> Vec<String> {
+        path.split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .rev()
+            .map(String::from)
+            .collect()
+    }
+
+    //空闲块不够的话什么都不改，已经分配出来的块还回去，返回false
     fn increase_size(
         &self,
         new_size: u32,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    ) -> bool {
         if new_size < disk_inode.size {
-            return;
+            return true;
         }
         let blocks_needed = disk_inode.blocks_num_needed(new_size);
         let mut v: Vec<u32> = Vec::new();
         for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
+            match fs.alloc_data() {
+                Some(block_id) => v.push(block_id),
+                None => {
+                    for block_id in v.into_iter() {
+                        fs.dealloc_data(block_id);
+                    }
+                    return false;
+                }
+            }
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
+        true
     }
 
+    //在这个目录里加一个名字为name的目录项要新分配多少块，有能复用的空目录项就不用分配
+    fn dirent_blocks_needed(&self, name: &str, dir_inode: &DiskInode) -> u32 {
+        let needed = dirent_slots(name.len(), self.long_names);
+        if self.read_dirents(dir_inode)
+            .iter()
+            .any(|dirent| dirent.name.is_empty() && dirent.slots >= needed) {
+            return 0;
+        }
+        dir_inode.blocks_num_needed(dir_inode.size + (needed * DIRENT_SZ) as u32)
+    }
+
+    //不带属主的几个create都是root的，权限用默认的
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_as(name, DEFAULT_FILE_MODE, 0, 0)
+    }
+
+    pub fn create_as(&self, name: &str, mode: u16, uid: u32, gid: u32) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File, 0, mode, uid, gid)
+    }
+
+    //新建一个子目录，里面自带.和..两个目录项
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_dir_as(name, DEFAULT_DIR_MODE, 0, 0)
+    }
+
+    pub fn create_dir_as(&self, name: &str, mode: u16, uid: u32, gid: u32) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory, 2 * DIRENT_SZ as u32, mode, uid, gid)
+    }
+
+    //属主和权限跟着inode一起写下去，不会出现一个先属于root的新文件
+    //data_size是新inode建好之后马上要写进去的数据大小，空闲块要连它一起算
+    fn create_inode(
+        &self,
+        name: &str,
+        type_: DiskInodeType,
+        data_size: u32,
+        mode: u16,
+        uid: u32,
+        gid: u32,
+    ) -> Option<Arc<Inode>> {
+        if !self.valid_name(name) {
+            return None;
+        }
+        let _tx = self.transaction();
         let mut fs = self.fs.lock();
-        if self.modify_disk_inode(|root_inode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
+        let dirent_blocks = self.modify_disk_inode(|dir_inode| {
+            // only a directory can hold new files
+            if !dir_inode.is_dir() {
+                return None;
+            }
             // has the file been created?
-            self.find_inode_id(name, root_inode)
-        }).is_some() {
+            if self.find_inode_id(name, dir_inode).is_some() {
+                return None;
+            }
+            Some(self.dirent_blocks_needed(name, dir_inode))
+        })?;
+        //新目录自己还要放.和..，符号链接还要放target，空闲块不够就什么都不建
+        if (dirent_blocks + DiskInode::total_blocks(data_size)) as usize > fs.free_data_blocks() {
             return None;
         }
         // create a new file
@@ -192,22 +352,27 @@ impl Inode {
             new_inode_block_id as usize,
             Arc::clone(&self.block_device)
         ).lock().modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-            new_inode.initialize(DiskInodeType::File);
-        });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+            let is_dir = type_ == DiskInodeType::Directory;
+            new_inode.initialize(type_);
+            new_inode.mode = mode & MODE_MASK;
+            new_inode.uid = uid;
+            new_inode.gid = gid;
+            if is_dir {
+                // "." points to the new directory itself and ".." to us
+                assert!(self.increase_size(2 * DIRENT_SZ as u32, new_inode, &mut fs));
+                new_inode.write_at(
+                    0,
+                    &encode_dirent(".", new_inode_id, 1, self.long_names),
+                    &self.block_device,
+                );
+                new_inode.write_at(
+                    DIRENT_SZ,
+                    &encode_dirent("..", self.my_inode_id, 1, self.long_names),
+                    &self.block_device,
+                );
+            }
         });
+        self.insert_dirent(name, new_inode_id, &mut fs);
         // release efs lock manually because we will acquire it again in Inode::new
         drop(fs);
         // return inode
@@ -218,45 +383,190 @@ impl Inode {
         )))
     }
 
-    //这个函数只允许ROOT调用
-    //一旦建立了linker，根目录下面的两个DirEntry的地位就是差不多的
-    pub fn create_linker(&self, name: &str, old_name: &str) -> Option<Arc<Inode>> {
-        let mut fs = self.fs.lock();
+    //新建一个指向target的符号链接，target不检查，可以指向不存在的东西
+    pub fn create_symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        self.create_symlink_as(name, target, 0, 0)
+    }
+
+    //符号链接本身的权限总是rwxrwxrwx，真正起作用的是它指向的东西的权限
+    pub fn create_symlink_as(&self, name: &str, target: &str, uid: u32, gid: u32) -> Option<Arc<Inode>> {
+        if target.is_empty() || target.len() > MAX_FILE_SIZE {
+            return None;
+        }
+        //新建和写内容放在同一个事务里，不会留下一个空的链接
+        let _tx = self.transaction();
+        let link = self.create_inode(
+            name,
+            DiskInodeType::Symlink,
+            target.len() as u32,
+            SYMLINK_MODE,
+            uid,
+            gid,
+        )?;
+        link.write_at(0, target.as_bytes());
+        Some(link)
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+
+    //符号链接指向的路径，不是符号链接返回None
+    pub fn read_link(&self) -> Option<String> {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_symlink() {
+                return None;
+            }
+            let mut data = vec![0u8; disk_inode.size as usize];
+            disk_inode.read_at(0, &mut data, &self.block_device);
+            String::from_utf8(data).ok()
+        })
+    }
 
+    //一旦建立了linker，目录下面的两个DirEntry的地位就是差不多的
+    pub fn create_linker(&self, name: &str, old_name: &str) -> Option<Arc<Inode>> {
         //先检查原来的文件是否存在
-        if self.modify_disk_inode(|root_inode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
-            // has the file been created?
-            self.find_inode_id(old_name, root_inode)
-        }).is_none() {
+        let old_inode = self.find(old_name)?;
+        self.link(name, &old_inode)
+    }
+
+    //在本目录下新建一个目录项，指向已经存在的target
+    //就不用新建inode了，target可以在别的目录里面
+    //目录不允许建立硬链接，不然..就乱套了
+    pub fn link(&self, name: &str, target: &Inode) -> Option<Arc<Inode>> {
+        if target.is_dir() || !self.valid_name(name) {
             return None;
         }
+        let _tx = self.transaction();
+        let mut fs = self.fs.lock();
+        let dirent_blocks = self.modify_disk_inode(|dir_inode| {
+            if !dir_inode.is_dir() || self.find_inode_id(name, dir_inode).is_some() {
+                return None;
+            }
+            Some(self.dirent_blocks_needed(name, dir_inode))
+        })?;
+        if dirent_blocks as usize > fs.free_data_blocks() {
+            return None;
+        }
+        let inode_id = target.my_inode_id;
+        self.insert_dirent(name, inode_id, &mut fs);
+        target.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink += 1;
+            disk_inode.touch_ctime();
+        });
+        // release efs lock manually because we will acquire it again in Inode::new
+        drop(fs);
+        // return inode
+        Some(Arc::new(Self::new(
+            inode_id,
+            self.fs.clone(),
+            self.block_device.clone(),
+        )))
+    }
 
-        //就不用新建inode了
-        //先获取旧的文件名和inode-id
-        //再新建目录项就可以了
-        let mut inode_id = 0;
-        //新建一个目录项
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
+    //目录项里面放不下的名字，还有空名字、.、..和带/的名字都不能用来新建
+    //老格式的镜像最多27个字节，长名字格式最多255个字节
+    pub fn valid_name(&self, name: &str) -> bool {
+        let limit = self.fs.lock().name_length_limit();
+        Self::valid_name_with_limit(name, limit)
+    }
+
+    //还没有镜像的时候（比如打包之前）用这个检查
+    pub fn valid_name_with_limit(name: &str, limit: usize) -> bool {
+        !name.is_empty()
+            && name.len() <= limit
+            && name != "."
+            && name != ".."
+            && !name.contains('/')
+    }
+
+    //优先复用被unlink空出来的、足够大的目录项，多出来的槽位还是空目录项
+    //没有空位再追加到最后，要的空闲块调用者先用dirent_blocks_needed检查过
+    fn insert_dirent(&self, name: &str, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        let needed = dirent_slots(name.len(), self.long_names);
+        self.modify_disk_inode(|dir_inode| {
+            let free = self.read_dirents(dir_inode)
+                .into_iter()
+                .find(|dirent| dirent.name.is_empty() && dirent.slots >= needed);
+            let slot = match free {
+                Some(free) => {
+                    if free.slots > needed {
+                        let rest = encode_dirent("", 0, free.slots - needed, self.long_names);
+                        dir_inode.write_at((free.slot + needed) * DIRENT_SZ, &rest, &self.block_device);
+                    }
+                    free.slot
+                }
+                None => {
+                    // increase size
+                    let slot_count = (dir_inode.size as usize) / DIRENT_SZ;
+                    assert!(self.increase_size(((slot_count + needed) * DIRENT_SZ) as u32, dir_inode, fs));
+                    slot_count
+                }
+            };
             // write dirent
-            let inode_id = self.find_inode_id(old_name, root_inode).unwrap();//因为前面已经检查过合法性了
-            //所以这个inode-id一定是合法的
-            let dirent = DirEntry::new(name, inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
+            let dirent = encode_dirent(name, inode_id, needed, self.long_names);
+            dir_inode.write_at(
+                slot * DIRENT_SZ,
+                &dirent,
                 &self.block_device,
             );
+            dir_inode.touch_mtime();
+        });
+    }
+
+    //把空目录项挤掉，目录的大小和占用的数据块也跟着缩小
+    //返回去掉了多少个空目录项
+    pub fn compact(&self) -> usize {
+        let _tx = self.transaction();
+        let mut fs = self.fs.lock();
+        self.modify_disk_inode(|dir_inode| {
+            if !dir_inode.is_dir() {
+                return 0;
+            }
+            //整个目录要在一个事务里重写，太大了日志放不下就不压缩
+            if dir_inode.data_blocks() as usize > self.journal.resize_step() {
+                return 0;
+            }
+            let dirents = self.read_dirents(dir_inode);
+            let removed = dirents.iter().filter(|dirent| dirent.name.is_empty()).count();
+            if removed == 0 {
+                return 0;
+            }
+            let mut data: Vec<u8> = Vec::new();
+            for dirent in dirents.iter().filter(|dirent| !dirent.name.is_empty()) {
+                let slots = dirent_slots(dirent.name.len(), self.long_names);
+                data.extend(encode_dirent(&dirent.name, dirent.inode_number, slots, self.long_names));
+            }
+            //整个目录重新写一遍
+            for data_block in dir_inode.clear_size(&self.block_device).into_iter() {
+                fs.dealloc_data(data_block);
+            }
+            //刚释放的块比重新写要的多，不会不够
+            assert!(self.increase_size(data.len() as u32, dir_inode, &mut fs));
+            dir_inode.write_at(0, &data, &self.block_device);
+            removed
+        })
+    }
+
+    //删掉name这个目录项，返回它原来指向的inode，nlink也跟着减一
+    //减到0以后要不要马上回收由调用者决定，因为内核里可能还有人打开着它
+    pub fn unlink(&self, name: &str) -> Option<Arc<Inode>> {
+        //.和..是目录自己的结构，不能删
+        if name.is_empty() || name == "." || name == ".." {
+            return None;
+        }
+        let _tx = self.transaction();
+        let fs = self.fs.lock();
+        //用一个一样大的空目录项把它盖掉
+        let inode_id = self.rewrite_dirent(name, "", 0)?.inode_number;
+        self.modify_inode_by_id(&fs, inode_id, |disk_inode| {
+            disk_inode.nlink = disk_inode.nlink.saturating_sub(1);
+            disk_inode.touch_ctime();
         });
         // release efs lock manually because we will acquire it again in Inode::new
         drop(fs);
-        // return inode
         Some(Arc::new(Self::new(
             inode_id,
             self.fs.clone(),
@@ -264,63 +574,239 @@ impl Inode {
         )))
     }
 
-    //这个函数也只允许ROOT调用
-    //这要怎么写······不如我改一下好了，比如说增加一个valid位？
-    //todo:fix return type, Option<bool>
-    pub fn delete_linker(&self, name: &str) -> bool{
-        let fs = self.fs.lock();
+    //把本目录里叫name的目录项原地改成指向inode_id的new_name，占的槽位不变，
+    //所以new_name要么是空的（删掉），要么和name一样长（rename覆盖目标的时候就是name本身）
+    //返回原来的目录项，找不到返回None
+    fn rewrite_dirent(&self, name: &str, new_name: &str, inode_id: u32) -> Option<DirSlot> {
+        self.modify_disk_inode(|dir_inode| {
+            if !dir_inode.is_dir() {
+                return None;
+            }
+            let dirent = self.read_dirents(dir_inode)
+                .into_iter()
+                .find(|dirent| dirent.name == name)?;
+            let data = encode_dirent(new_name, inode_id, dirent.slots, self.long_names);
+            assert_eq!(
+                dir_inode.write_at(dirent.slot * DIRENT_SZ, &data, &self.block_device),
+                data.len(),
+            );
+            dir_inode.touch_mtime();
+            Some(dirent)
+        })
+    }
 
-        //先检查想要unlink的文件是否存在
-        if self.modify_disk_inode(|root_inode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
-            // has the file been created?
-            self.find_inode_id(name, root_inode)
-        }).is_none() {
-            return false;
+    //按inode编号读写别的inode，调用的时候要拿着fs的锁
+    fn read_inode_by_id<V>(&self, fs: &EasyFileSystem, inode_id: u32, f: impl FnOnce(&DiskInode) -> V) -> V {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(
+            block_id as usize,
+            Arc::clone(&self.block_device)
+        ).lock().read(block_offset, f)
+    }
+
+    fn modify_inode_by_id<V>(&self, fs: &EasyFileSystem, inode_id: u32, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(
+            block_id as usize,
+            Arc::clone(&self.block_device)
+        ).lock().modify(block_offset, f)
+    }
+
+    //把本目录下的old_name改名成new_dir下的new_name，new_dir可以就是自己
+    //new_name已经存在的话就把它替换掉：文件只能替换文件，目录只能替换空目录；
+    //no_replace为true的时候new_name存在就失败
+    //目录不能挪到它自己下面去，挪到别的目录下面的时候它的..也跟着改
+    //整个操作在一个事务里，要么全做完要么什么都没发生
+    //失败返回None，成功返回被替换掉的inode（nlink已经减过了，回收不回收由调用者决定）
+    pub fn rename(
+        &self,
+        old_name: &str,
+        new_dir: &Inode,
+        new_name: &str,
+        no_replace: bool,
+    ) -> Option<Option<Arc<Inode>>> {
+        if old_name.is_empty() || old_name == "." || old_name == ".." || !new_dir.valid_name(new_name) {
+            return None;
+        }
+        let _tx = self.transaction();
+        let mut fs = self.fs.lock();
+        let find = |dir: &Inode, name: &str| dir.read_disk_inode(|dir_inode| {
+            if dir_inode.is_dir() { Some(dir.find_inode_id(name, dir_inode)) } else { None }
+        });
+        let src_id = find(self, old_name)??;
+        let dst_id = find(new_dir, new_name)?;
+        //两个名字本来就指向同一个inode，什么都不用做
+        if dst_id == Some(src_id) {
+            return Some(None);
+        }
+        let src_is_dir = self.read_inode_by_id(&fs, src_id, |disk_inode| disk_inode.is_dir());
+        if src_is_dir {
+            //从new_dir沿着..往上走到根，路上碰到自己就是要挪到自己下面去
+            let mut id = new_dir.my_inode_id;
+            loop {
+                if id == src_id {
+                    return None;
+                }
+                if id == 0 {
+                    break;
+                }
+                id = self.read_inode_by_id(&fs, id, |disk_inode| self.find_inode_id("..", disk_inode))?;
+            }
+        }
+        //要新加目录项的话先看看空闲块够不够，不够就什么都不改
+        if dst_id.is_none()
+            && new_dir.read_disk_inode(|dir_inode| new_dir.dirent_blocks_needed(new_name, dir_inode)) as usize
+                > fs.free_data_blocks() {
+            return None;
+        }
+        if let Some(dst_id) = dst_id {
+            if no_replace {
+                return None;
+            }
+            let dst_ok = self.read_inode_by_id(&fs, dst_id, |disk_inode| {
+                if disk_inode.is_dir() != src_is_dir {
+                    return false;
+                }
+                !disk_inode.is_dir() || self.read_dirents(disk_inode)
+                    .iter()
+                    .all(|dirent| dirent.name.is_empty() || dirent.name == "." || dirent.name == "..")
+            });
+            if !dst_ok {
+                return None;
+            }
+            new_dir.rewrite_dirent(new_name, new_name, src_id);
+            self.modify_inode_by_id(&fs, dst_id, |disk_inode| {
+                disk_inode.nlink = disk_inode.nlink.saturating_sub(1);
+                disk_inode.touch_ctime();
+            });
+        } else {
+            new_dir.insert_dirent(new_name, src_id, &mut fs);
         }
+        self.rewrite_dirent(old_name, "", 0);
+        if src_is_dir && new_dir.my_inode_id != self.my_inode_id {
+            let src = Self::new_locked(src_id, &fs, self);
+            src.rewrite_dirent("..", "..", new_dir.my_inode_id);
+        }
+        self.modify_inode_by_id(&fs, src_id, |disk_inode| disk_inode.touch_ctime());
+        drop(fs);
+        Some(dst_id.map(|dst_id| Arc::new(Self::new(
+            dst_id,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ))))
+    }
 
-        //就是不用考虑只剩下这一个DirEntry到文件还有link的情况，直接删除即可
-        //把那个Entry设置成不合法即可
-        let mut result = false;
-        //新建一个目录项
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let mut dirent = DirEntry::empty();
-            for i in 0..file_count {
-                assert_eq!(
-                    root_inode.read_at(
-                        DIRENT_SZ * i,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
-                //TODO这里要修改输出，能够返回文件名鸭
-                // fs_println!("get_my_data::dirent name is {}, inode number is {}",dirent.name(),dirent.inode_number());
-                if dirent.name() == name {
-                    fs_println!("get_my_data::dirent name is {}, inode number is {},but return None",
-                        dirent.name(),dirent.inode_number());
-                    //如果找到了，那么就把空的写进去
-                    //就是用一个不合法的内容替换的意思
-                    let mut dirent_unlink = DirEntry::empty();
-                    assert_eq!(
-                        root_inode.write_at(
-                            i * DIRENT_SZ,
-                            dirent_unlink.as_bytes(),
-                            &self.block_device,
-                        ),
-                        DIRENT_SZ,
-                    );
-                    result = true;
+    //没有"打开的文件"这回事的调用者（比如easy-fs-fuse）用这个
+    //最后一个链接删掉以后就直接回收
+    //小文件的删除和回收在一个事务里；大文件一个事务放不下，先删目录项，再分几个事务回收
+    pub fn delete_linker(&self, name: &str) -> bool {
+        //目录要用delete_dir删除
+        let small = match self.find(name) {
+            Some(inode) if !inode.is_dir() => inode.fits_one_step(),
+            _ => return false,
+        };
+        let _tx = if small { Some(self.transaction()) } else { None };
+        match self.unlink(name) {
+            Some(inode) => {
+                if inode.nlink() == 0 {
+                    inode.reclaim();
                 }
+                true
             }
+            None => false,
+        }
+    }
+
+    //删除一个空的子目录的目录项，里面只能剩下.和..
+    pub fn remove_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        let dir = self.find(name)?;
+        if !dir.is_dir() || !dir.is_empty_dir() {
+            return None;
+        }
+        self.unlink(name)
+    }
+
+    //删除空目录，目录的数据块和inode也一起释放掉
+    //空目录大了也只是一堆空目录项，和delete_linker一样大的时候分开回收
+    pub fn delete_dir(&self, name: &str) -> bool {
+        let small = self.find(name).map_or(true, |dir| dir.fits_one_step());
+        let _tx = if small { Some(self.transaction()) } else { None };
+        match self.remove_dir(name) {
+            Some(dir) => {
+                if dir.nlink() == 0 {
+                    dir.reclaim();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_empty_dir(&self) -> bool {
+        self.ls()
+            .iter()
+            .all(|name| name == "." || name == "..")
+    }
+
+    pub fn nlink(&self) -> u32 {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.nlink)
+    }
+
+    //(atime, mtime, ctime)，单位是毫秒
+    pub fn times(&self) -> (u64, u64, u64) {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| (disk_inode.atime, disk_inode.mtime, disk_inode.ctime))
+    }
+
+    //权限位，不包括文件类型
+    pub fn mode(&self) -> u16 {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.mode)
+    }
+
+    //(uid, gid)
+    pub fn owner(&self) -> (u32, u32) {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| (disk_inode.uid, disk_inode.gid))
+    }
+
+    //谁能改权限和属主由调用的人检查，这里只管写进去
+    pub fn chmod(&self, mode: u16) {
+        let _tx = self.transaction();
+        let _ = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = mode & MODE_MASK;
+            disk_inode.touch_ctime();
+        });
+    }
+
+    pub fn chown(&self, uid: u32, gid: u32) {
+        let _tx = self.transaction();
+        let _ = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.uid = uid;
+            disk_inode.gid = gid;
+            disk_inode.touch_ctime();
         });
-        // release efs lock manually because we will acquire it again in Inode::new
-        drop(fs);
-        // return nothing
-        return result;
+    }
+
+    //已经没有目录项指向它了，把数据块和inode本身都还回去
+    //根目录永远不回收
+    pub fn reclaim(&self) {
+        if self.my_inode_id == 0 {
+            return;
+        }
+        //大文件先分几个事务截断到一步就能释放完的大小，最后一步和释放inode放在一个事务里
+        //中途崩溃的话留下一个没有目录项指向的inode，check可以把它回收掉
+        let step = self.journal.resize_step();
+        let size = self.size() as usize;
+        if size > step.saturating_mul(BLOCK_SZ) {
+            self.resize((step * BLOCK_SZ) as u32);
+        }
+        let _tx = self.transaction();
+        self.clear();
+        self.fs.lock().dealloc_inode(self.my_inode_id);
     }
 
     //只有根目录可以调用
@@ -329,8 +815,9 @@ impl Inode {
 
         //先检查想要计数的文件是否存在
         if self.modify_disk_inode(|root_inode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
+            if !root_inode.is_dir() {
+                return None;
+            }
             // has the file been created?
             self.find_inode_id(name, root_inode)
         }).is_none() {
@@ -346,42 +833,25 @@ impl Inode {
         //新建一个目录项
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let mut dirent = DirEntry::empty();
-            for i in 0..file_count {
-                assert_eq!(
-                    root_inode.read_at(
-                        DIRENT_SZ * i,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
+            let dirents = self.read_dirents(root_inode);
+            for dirent in dirents.iter() {
                 //得到了自己的inode-number
-                if dirent.name() == name {
+                if dirent.name == name {
                     fs_println!("count_files::get_inode_id::dirent name is {}, inode number is {},but return None",
-                        dirent.name(),dirent.inode_number());
+                        dirent.name,dirent.inode_number);
                     //如果找到了，那么就把空的写进去
                     //就是用一个不合法的内容替换的意思
-                    inode_id = dirent.inode_number();
+                    inode_id = dirent.inode_number;
                     break;
                 }
             }
 
             //下面是计数的内容
-            for i in 0..file_count {
-                assert_eq!(
-                    root_inode.read_at(
-                        DIRENT_SZ * i,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
+            for dirent in dirents.iter() {
                 //得到了自己的inode-number
-                if dirent.inode_number() == inode_id {
+                if dirent.inode_number == inode_id {
                     fs_println!("count_files::counting::dirent name is {}, inode number is {},but return None",
-                        dirent.name(),dirent.inode_number());
+                        dirent.name,dirent.inode_number);
                     //如果找到了，那么就把空的写进去
                     //就是用一个不合法的内容替换的意思
                     counter = counter + 1;
@@ -402,22 +872,12 @@ impl Inode {
         //新建一个目录项
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let mut dirent = DirEntry::empty();
             //下面是计数的内容
-            for i in 0..file_count {
-                assert_eq!(
-                    root_inode.read_at(
-                        DIRENT_SZ * i,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
+            for dirent in self.read_dirents(root_inode) {
                 //得到了自己的inode-number
-                if dirent.inode_number() == self.my_inode_id {
+                if dirent.inode_number == self.my_inode_id {
                     fs_println!("count_files::counting::dirent name is {}, inode number is {},",
-                        dirent.name(),dirent.inode_number());
+                        dirent.name,dirent.inode_number);
                     //如果找到了，那么就把空的写进去
                     //就是用一个不合法的内容替换的意思
                     counter = counter + 1;
@@ -438,22 +898,12 @@ impl Inode {
         //新建一个目录项
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let mut dirent = DirEntry::empty();
             //下面是计数的内容
-            for i in 0..file_count {
-                assert_eq!(
-                    root_inode.read_at(
-                        DIRENT_SZ * i,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
+            for dirent in self.read_dirents(root_inode) {
                 //得到了自己的inode-number
-                if dirent.inode_number() == id {
+                if dirent.inode_number == id {
                     fs_println!("count_files::counting::dirent name is {}, inode number is {},",
-                        dirent.name(),dirent.inode_number());
+                        dirent.name,dirent.inode_number);
                     //如果找到了，那么就把空的写进去
                     //就是用一个不合法的内容替换的意思
                     counter = counter + 1;
@@ -470,48 +920,134 @@ impl Inode {
     pub fn ls(&self) -> Vec<String> {
         let _ = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(
-                        i * DIRENT_SZ,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
-            }
-            v
+            //被unlink空出来的目录项不算
+            self.read_dirents(disk_inode)
+                .into_iter()
+                .filter(|dirent| !dirent.name.is_empty())
+                .map(|dirent| dirent.name)
+                .collect::<Vec<String>>()
         })
     }
 
+    //从目录里的第offset个字节开始列出目录项，getdents用
+    //返回的next是下一个目录项开始的位置，拿它再调一次就接着往下读；
+    //删掉的目录项还占着原来的位置，所以两次调用之间有删除也不会漏掉或者重复
+    pub fn read_dir_from(&self, offset: usize) -> Vec<DirEntryInfo> {
+        let fs = self.fs.lock();
+        let dirents = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Vec::new();
+            }
+            self.read_dirents(disk_inode)
+        });
+        //子目录的inode可能和自己在同一个块里，不能在上面的闭包里面读
+        dirents
+            .into_iter()
+            .filter(|dirent| !dirent.name.is_empty() && dirent.slot * DIRENT_SZ >= offset)
+            .map(|dirent| {
+                let (block_id, block_offset) = fs.get_disk_inode_pos(dirent.inode_number);
+                let (is_dir, is_symlink) = get_block_cache(
+                    block_id as usize,
+                    Arc::clone(&self.block_device)
+                ).lock().read(block_offset, |disk_inode: &DiskInode| {
+                    (disk_inode.is_dir(), disk_inode.is_symlink())
+                });
+                DirEntryInfo {
+                    inode_id: dirent.inode_number,
+                    is_dir,
+                    is_symlink,
+                    next: (dirent.slot + dirent.slots) * DIRENT_SZ,
+                    name: dirent.name,
+                }
+            })
+            .collect()
+    }
+
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
         let _ = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.touch_atime();
             disk_inode.read_at(offset, buf, &self.block_device)
         })
     }
 
+    //只有变大的时候才要开事务，文件内容本身不进日志
+    //空闲块不够变大的时候只写原来的大小以内的部分，返回实际写了多少
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        let mut fs = self.fs.lock();
+        let new_size = (offset + buf.len()) as u32;
+        if new_size > self.size() && self.has_room_for(new_size) {
+            self.resize(new_size);
+        }
+        let _ = self.fs.lock();
         self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
+            if offset >= disk_inode.size as usize {
+                return 0;
+            }
+            disk_inode.touch_mtime();
             disk_inode.write_at(offset, buf, &self.block_device)
         })
     }
 
+    pub fn size(&self) -> u32 {
+        let _ = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size)
+    }
+
+    //把文件的大小改成new_size，变小的时候后面的数据块（包括用不到的索引块）都还回去，
+    //变大的时候多出来的部分读出来是0，超过MAX_FILE_SIZE或者空闲块不够就返回false
+    pub fn truncate(&self, new_size: u32) -> bool {
+        if new_size as usize > MAX_FILE_SIZE {
+            return false;
+        }
+        self.has_room_for(new_size) && self.resize(new_size)
+    }
+
+    //变大到new_size要新分配的块，空闲块够不够
+    fn has_room_for(&self, new_size: u32) -> bool {
+        let needed = DiskInode::total_blocks(new_size).saturating_sub(DiskInode::total_blocks(self.size()));
+        needed as usize <= self.fs.lock().free_data_blocks()
+    }
+
     pub fn clear(&self) {
-        let mut fs = self.fs.lock();
-        self.modify_disk_inode(|disk_inode| {
-            let size = disk_inode.size;
-            let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-            for data_block in data_blocks_dealloc.into_iter() {
-                fs.dealloc_data(data_block);
+        self.resize(0);
+    }
+
+    //把大小一步一步地改成new_size，每一步一个事务，分配或者释放的数据块不超过resize_step个，
+    //这样每个事务都放得进日志；每一步结束的时候文件都是完整的，只是大小在新旧之间
+    //外面已经开着事务的话这几步会并到外面的事务里，所以只有小文件才能这样用
+    //变大的时候空闲块不够了就停在中间，返回false
+    fn resize(&self, new_size: u32) -> bool {
+        let step = self.journal.resize_step();
+        loop {
+            let _tx = self.transaction();
+            let mut fs = self.fs.lock();
+            let done = self.modify_disk_inode(|disk_inode| {
+                disk_inode.touch_mtime();
+                let blocks = disk_inode.data_blocks() as usize;
+                if new_size > disk_inode.size {
+                    let next = ((blocks.saturating_add(step)).saturating_mul(BLOCK_SZ))
+                        .min(new_size as usize);
+                    if !self.increase_size(next as u32, disk_inode, &mut fs) {
+                        return None;
+                    }
+                } else if new_size < disk_inode.size {
+                    let next = (blocks.saturating_sub(step) * BLOCK_SZ).max(new_size as usize);
+                    for data_block in disk_inode.decrease_size(next as u32, &self.block_device).into_iter() {
+                        fs.dealloc_data(data_block);
+                    }
+                }
+                Some(disk_inode.size == new_size)
+            });
+            match done {
+                Some(true) => return true,
+                Some(false) => {}
+                None => return false,
             }
-        });
+        }
+    }
+
+    //一个事务就能把它的数据块都释放完
+    fn fits_one_step(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.data_blocks() as usize) <= self.journal.resize_step()
     }
 }
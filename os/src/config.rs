@@ -22,6 +22,15 @@ pub const TASK_PRIORITY_INIT: usize = 16;
 // pub const PAGE_SIZE: usize = 0x1000;//16进制的100就是4096个字节
 // pub const PAGE_SIZE_BITS: usize = 0xc;//表示我要用多少位的数表示一个page,也就是page offset的位数。12位
 pub const MEMORY_MAP_SIZE: usize = 0x4_000_000;//表示进行一次内存映射最多映射多少字节
+pub const MMAP_BASE: usize = 0x4000_0000;//mmap不指定地址的时候从这里往上找空的地方
+//用户地址空间的上界：Sv39里只有低半部分[0, 2^38)是给用户的，
+//再往上的地址页表索引会和最高处的跳板、TrapContext撞在一起
+pub const USER_SPACE_END: usize = 1 << 38;
+
+//for swap
+pub const SWAP_FILE: &str = ".swap";//放在根目录下的交换文件
+pub const SWAP_SIZE: usize = 0x80_0000;//交换文件多大，镜像里要留出这么多空间，不能超过easy-fs的MAX_FILE_SIZE
+pub const SWAP_RESERVE_FRAMES: usize = 8;//缺页的时候空闲页帧少于这么多就先换出，留给页表和内核用
 
 // pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 // pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
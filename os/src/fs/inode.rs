@@ -1,19 +1,28 @@
 use easy_fs::{
     EasyFileSystem,
     Inode,
+    MAX_FILE_SIZE,
+    DEFAULT_FILE_MODE,
+    MODE_MASK,
+    set_clock,
 };
 use crate::drivers::BLOCK_DEVICE;
+use crate::timer::get_time_ms;
 use alloc::sync::Arc;
 use lazy_static::*;
 use bitflags::*;
 use alloc::vec::Vec;
+use alloc::string::String;
+use alloc::collections::{BTreeMap, BTreeSet};
 use spin::Mutex;
-use super::File;
+use super::{File, FileType, SEEK_SET, SEEK_CUR, SEEK_END};
 use crate::mm::UserBuffer;
 
 pub struct OSInode {
     readable: bool,
     writable: bool,
+    //O_APPEND：每次write之前都先把offset挪到文件末尾
+    append: bool,
     inner: Mutex<OSInodeInner>,
 }
 
@@ -26,11 +35,15 @@ impl OSInode {
     pub fn new(
         readable: bool,
         writable: bool,
+        append: bool,
         inode: Arc<Inode>,
     ) -> Self {
+        let inode_id = inode.get_my_inode_id().unwrap();
+        *OPEN_INODES.lock().entry(inode_id).or_insert(0) += 1;
         Self {
             readable,
             writable,
+            append,
             inner: Mutex::new(OSInodeInner {
                 offset: 0,
                 inode,
@@ -67,8 +80,54 @@ impl OSInode {
     }
 }
 
+//最后一个OSInode关掉的时候，如果已经没有目录项指向它了，就在这时回收
+impl Drop for OSInode {
+    fn drop(&mut self) {
+        let inode = self.inner.lock().inode.clone();
+        let inode_id = inode.get_my_inode_id().unwrap();
+        let mut open_inodes = OPEN_INODES.lock();
+        let count = open_inodes.get_mut(&inode_id).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            open_inodes.remove(&inode_id);
+            if inode.nlink() == 0 {
+                inode.reclaim();
+            }
+        }
+    }
+}
+
+lazy_static! {
+    //每个inode现在被多少个OSInode打开着
+    static ref OPEN_INODES: Mutex<BTreeMap<u32, usize>> = Mutex::new(BTreeMap::new());
+    //内核自己在用的inode，用户打不开、删不掉、也不能被rename挪走或者覆盖
+    static ref PINNED_INODES: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+}
+
+//内核自己一直在用的inode（交换文件）记成永远打开着，
+//这样就算在pin之前被打开过，之后也不会把它的数据块回收掉
+pub fn pin_inode(inode: &Arc<Inode>) {
+    let inode_id = inode.get_my_inode_id().unwrap();
+    *OPEN_INODES.lock().entry(inode_id).or_insert(0) += 1;
+    PINNED_INODES.lock().insert(inode_id);
+}
+
+pub fn is_pinned(inode: &Inode) -> bool {
+    PINNED_INODES.lock().contains(&inode.get_my_inode_id().unwrap())
+}
+
+//unlink之后调用，nlink到0并且没有人打开着才真正回收
+fn release_if_unused(inode: &Arc<Inode>) {
+    let open_inodes = OPEN_INODES.lock();
+    if inode.nlink() == 0 && !open_inodes.contains_key(&inode.get_my_inode_id().unwrap()) {
+        inode.reclaim();
+    }
+}
+
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
+        //inode的时间戳用开机以来的毫秒数
+        set_clock(|| get_time_ms() as u64);
         let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
@@ -89,75 +148,410 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        //后面这几个尽量取和Linux一样的值，
+        //只有APPEND在Linux里是1 << 10，和上面的TRUNC撞了，只好换一个
+        const EXCL = 1 << 7;
+        const APPEND = 1 << 11;
+        const DIRECTORY = 1 << 16;
+        const CLOEXEC = 1 << 19;
     }
 }
 
 impl OpenFlags {
-    /// Do not check validity for simplicity
     /// Return (readable, writable)
-    pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
-            (true, false)
+    /// 标志组合起来没有意义的时候返回None：
+    /// WRONLY和RDWR同时出现、只读的时候要TRUNC、没有CREATE却有EXCL、
+    /// DIRECTORY又要写或者要创建
+    pub fn read_write(&self) -> Option<(bool, bool)> {
+        let (readable, writable) = if self.contains(Self::WRONLY | Self::RDWR) {
+            return None;
         } else if self.contains(Self::WRONLY) {
             (false, true)
-        } else {
+        } else if self.contains(Self::RDWR) {
             (true, true)
+        } else {
+            (true, false)
+        };
+        if self.contains(Self::TRUNC) && !writable {
+            return None;
+        }
+        if self.contains(Self::EXCL) && !self.contains(Self::CREATE) {
+            return None;
+        }
+        if self.contains(Self::DIRECTORY)
+            && (writable || self.intersects(Self::CREATE | Self::TRUNC | Self::APPEND)) {
+            return None;
         }
+        Some((readable, writable))
     }
 }
 
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
-    let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        kernel_println!("[open_file] creating file");
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            // kernel_println!("[open_file] creating file");
-            inode.clear();
-            Some(Arc::new(OSInode::new(
-                readable,
-                writable,
-                inode,
-            )))
-        } else {
-            // create file
-            ROOT_INODE.create(name)
-                .map(|inode| {
-                    Arc::new(OSInode::new(
-                        readable,
-                        writable,
-                        inode,
-                    ))
-                })
+//进程的身份，检查文件权限用
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Cred {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Cred {
+    pub const ROOT: Cred = Cred { uid: 0, gid: 0 };
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+}
+
+//要检查的权限，和access()的R_OK、W_OK、X_OK一样
+pub const MAY_READ: u16 = 4;
+pub const MAY_WRITE: u16 = 2;
+pub const MAY_EXEC: u16 = 1;
+const S_ISUID: u16 = 0o4000;
+const S_ISGID: u16 = 0o2000;
+const S_ISVTX: u16 = 0o1000;
+
+//属主、属组、其他人三组rwx里挑一组来比
+//root读写不受限制，执行的话至少要有一组有x，目录总是可以进
+//路径中间经过的目录不检查x，只检查最后一级和要改的目录
+pub fn may_access(inode: &Inode, cred: Cred, access: u16) -> bool {
+    let mode = inode.mode();
+    if cred.is_root() {
+        return access & MAY_EXEC == 0 || inode.is_dir() || mode & 0o111 != 0;
+    }
+    let (uid, gid) = inode.owner();
+    let bits = if cred.uid == uid {
+        mode >> 6
+    } else if cred.gid == gid {
+        mode >> 3
+    } else {
+        mode
+    } & 7;
+    bits & access == access
+}
+
+//往目录里加目录项要能写它、能进去
+fn may_modify_dir(dir: &Inode, cred: Cred) -> bool {
+    may_access(dir, cred, MAY_WRITE | MAY_EXEC)
+}
+
+//删掉或者挪走dir里的victim
+//带sticky位的目录（比如根目录）里，只有victim的主人、目录的主人和root可以
+fn may_remove(dir: &Inode, victim: &Inode, cred: Cred) -> bool {
+    if !may_modify_dir(dir, cred) {
+        return false;
+    }
+    cred.is_root()
+        || dir.mode() & S_ISVTX == 0
+        || victim.owner().0 == cred.uid
+        || dir.owner().0 == cred.uid
+}
+
+//把路径拆成(父目录, 最后一级的名字)
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
+    }
+}
+
+//把相对于cwd的路径拼成一个规范的绝对路径，getcwd要用
+//cwd本身必须是绝对路径
+pub fn join_path(cwd: &str, path: &str) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    let full_path = if path.starts_with('/') { [path, ""] } else { [cwd, path] };
+    for name in full_path.iter().flat_map(|p| p.split('/')) {
+        match name {
+            "" | "." => {}
+            ".." => { names.pop(); }
+            _ => names.push(name),
         }
+    }
+    let mut result = String::new();
+    for name in names {
+        result.push('/');
+        result.push_str(name);
+    }
+    if result.is_empty() {
+        result.push('/');
+    }
+    result
+}
+
+//以/开头的是绝对路径，否则就从dir开始往下找
+pub fn find_inode(dir: &Arc<Inode>, path: &str) -> Option<Arc<Inode>> {
+    if path.starts_with('/') {
+        ROOT_INODE.find_path(path)
     } else {
-        ROOT_INODE.find(name)
+        dir.find_path(path)
+    }
+}
+
+//和find_inode一样，但是最后一级是符号链接的话不跟过去，lstat和readlink要用
+pub fn find_inode_nofollow(dir: &Arc<Inode>, path: &str) -> Option<Arc<Inode>> {
+    match find_parent(dir, path) {
+        Some((parent, name)) if name != ".." => parent.find(name),
+        //"/"、"."和".."这种本身就不会是符号链接
+        _ => find_inode(dir, path),
+    }
+}
+
+//找到路径的父目录，顺便返回最后一级的名字
+fn find_parent<'a>(dir: &Arc<Inode>, path: &'a str) -> Option<(Arc<Inode>, &'a str)> {
+    let (parent_path, name) = split_path(path);
+    if name.is_empty() {
+        return None;
+    }
+    //"/a"拆出来的父目录是空串，但是它其实是根目录
+    let parent = if parent_path.is_empty() && path.starts_with('/') {
+        ROOT_INODE.clone()
+    } else {
+        find_inode(dir, parent_path)?
+    };
+    if !parent.is_dir() {
+        return None;
+    }
+    Some((parent, name))
+}
+
+//内核自己打开文件（比如initproc）用的，不检查权限
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+    open_file_at(&ROOT_INODE, path, flags, Cred::ROOT)
+}
+
+//按cred检查权限：读要r，写或者截断要w，新建文件要能改父目录
+pub fn open_file_at(
+    dir: &Arc<Inode>,
+    path: &str,
+    flags: OpenFlags,
+    cred: Cred,
+) -> Option<Arc<OSInode>> {
+    let (readable, writable) = flags.read_write()?;
+    let append = flags.contains(OpenFlags::APPEND);
+    if let Some(inode) = find_inode(dir, path) {
+        //CREATE | EXCL要求文件原来不存在
+        if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) || is_pinned(&inode) {
+            return None;
+        }
+        if flags.contains(OpenFlags::DIRECTORY) && !inode.is_dir() {
+            return None;
+        }
+        //目录只能以只读方式打开，也不能截断
+        if inode.is_dir() && (writable || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC)) {
+            return None;
+        }
+        //只有可写的打开带着TRUNC才清空，CREATE打开已经存在的文件不动它的内容
+        let truncate = flags.contains(OpenFlags::TRUNC) && writable;
+        if (readable && !may_access(&inode, cred, MAY_READ))
+            || (writable && !may_access(&inode, cred, MAY_WRITE)) {
+            return None;
+        }
+        if truncate {
+            // clear size
+            kernel_println!("[open_file] truncating file");
+            inode.truncate(0);
+        }
+        Some(Arc::new(OSInode::new(
+            readable,
+            writable,
+            append,
+            inode,
+        )))
+    } else if flags.contains(OpenFlags::CREATE) {
+        kernel_println!("[open_file] creating file");
+        // create file
+        let (parent, name) = find_parent(dir, path)?;
+        if !may_modify_dir(&parent, cred) {
+            return None;
+        }
+        parent.create_as(name, DEFAULT_FILE_MODE, cred.uid, cred.gid)
             .map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
                 Arc::new(OSInode::new(
                     readable,
                     writable,
-                    inode
+                    append,
+                    inode,
                 ))
             })
+    } else {
+        None
+    }
+}
+
+//exec和spawn用的：要是有x权限的普通文件，内核自己读，不需要r
+pub fn open_exec(dir: &Arc<Inode>, path: &str, cred: Cred) -> Option<Arc<OSInode>> {
+    let inode = find_inode(dir, path)?;
+    if inode.is_dir() || is_pinned(&inode) || !may_access(&inode, cred, MAY_EXEC) {
+        return None;
+    }
+    Some(Arc::new(OSInode::new(true, false, false, inode)))
+}
+
+//chdir用的：要是有x权限的目录，进不去的目录不能当工作目录
+pub fn open_dir(dir: &Arc<Inode>, path: &str, cred: Cred) -> Option<Arc<Inode>> {
+    let inode = find_inode(dir, path)?;
+    if !inode.is_dir() || !may_access(&inode, cred, MAY_EXEC) {
+        return None;
+    }
+    Some(inode)
+}
+
+pub fn mkdir(dir: &Arc<Inode>, path: &str, mode: u16, cred: Cred) -> bool {
+    if let Some((parent, name)) = find_parent(dir, path) {
+        may_modify_dir(&parent, cred)
+            && parent.create_dir_as(name, mode & MODE_MASK, cred.uid, cred.gid).is_some()
+    } else {
+        false
+    }
+}
+
+//只能删除空目录
+pub fn rmdir(dir: &Arc<Inode>, path: &str, cred: Cred) -> bool {
+    if let Some((parent, name)) = find_parent(dir, path) {
+        match parent.find(name) {
+            Some(victim) if may_remove(&parent, &victim, cred) => {}
+            _ => return false,
+        }
+        match parent.remove_dir(name) {
+            Some(dir) => {
+                release_if_unused(&dir);
+                true
+            }
+            None => false,
+        }
+    } else {
+        false
     }
 }
 
 //perhaps done
-pub fn get_inode_id(name: &str) -> Option<u32>{
-    ROOT_INODE.get_inode_id(name)
+pub fn get_inode_id(path: &str) -> Option<u32>{
+    find_inode(&ROOT_INODE, path).and_then(|inode| inode.get_my_inode_id())
 }
 
-// pub fn create(&self, name: &str,old_name: &str) -> Option<Arc<Inode>>
-pub fn create_linker(name:&str,old_name:&str) -> Option<Arc<Inode>>{
-    ROOT_INODE.create_linker(name,old_name)
+//新路径可以和旧路径不在同一个目录下面
+pub fn create_linker(
+    new_dir: &Arc<Inode>,
+    new_path: &str,
+    old_dir: &Arc<Inode>,
+    old_path: &str,
+    cred: Cred,
+) -> Option<Arc<Inode>>{
+    let old_inode = find_inode(old_dir, old_path)?;
+    let (parent, name) = find_parent(new_dir, new_path)?;
+    if !may_modify_dir(&parent, cred) {
+        return None;
+    }
+    parent.link(name, &old_inode)
 }
 
-// pub fn delete_linker(&self, name: &str) -> Option<bool>
-pub fn delete_linker(name: &str) -> bool{
-    ROOT_INODE.delete_linker(name)
+//在path处新建一个指向target的符号链接，target存不存在都可以
+pub fn symlink(dir: &Arc<Inode>, path: &str, target: &str, cred: Cred) -> bool {
+    if let Some((parent, name)) = find_parent(dir, path) {
+        may_modify_dir(&parent, cred)
+            && parent.create_symlink_as(name, target, cred.uid, cred.gid).is_some()
+    } else {
+        false
+    }
+}
+
+//目录要用rmdir删除
+pub fn delete_linker(dir: &Arc<Inode>, path: &str, cred: Cred) -> bool{
+    if let Some((parent, name)) = find_parent(dir, path) {
+        match parent.find(name) {
+            Some(inode) if !inode.is_dir() && !is_pinned(&inode) && may_remove(&parent, &inode, cred) => match parent.unlink(name) {
+                Some(inode) => {
+                    release_if_unused(&inode);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    } else {
+        false
+    }
+}
+
+//两个路径各自按自己的dir解析，被替换掉的文件没人打开着的话就回收
+pub fn rename(
+    old_dir: &Arc<Inode>,
+    old_path: &str,
+    new_dir: &Arc<Inode>,
+    new_path: &str,
+    no_replace: bool,
+    cred: Cred,
+) -> bool {
+    let (old_parent, old_name) = match find_parent(old_dir, old_path) {
+        Some(pair) => pair,
+        None => return false,
+    };
+    let (new_parent, new_name) = match find_parent(new_dir, new_path) {
+        Some(pair) => pair,
+        None => return false,
+    };
+    //挪走旧的和替换掉新的都算删除；目录换了父目录还要改它的..，所以要能写它
+    let allowed = match (old_parent.find(old_name), new_parent.find(new_name)) {
+        (Some(victim), replaced) => {
+            !is_pinned(&victim)
+                && replaced.as_ref().map_or(true, |replaced| !is_pinned(replaced))
+                && may_remove(&old_parent, &victim, cred)
+                && may_modify_dir(&new_parent, cred)
+                && replaced.map_or(true, |replaced| may_remove(&new_parent, &replaced, cred))
+                && (!victim.is_dir()
+                    || old_parent.get_my_inode_id() == new_parent.get_my_inode_id()
+                    || may_access(&victim, cred, MAY_WRITE))
+        }
+        (None, _) => false,
+    };
+    if !allowed {
+        return false;
+    }
+    match old_parent.rename(old_name, &new_parent, new_name, no_replace) {
+        Some(replaced) => {
+            if let Some(inode) = replaced {
+                release_if_unused(&inode);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+//只有文件的主人和root可以改权限
+pub fn chmod(dir: &Arc<Inode>, path: &str, mode: u16, cred: Cred) -> bool {
+    let inode = match find_inode(dir, path) {
+        Some(inode) => inode,
+        None => return false,
+    };
+    let (uid, gid) = inode.owner();
+    if !cred.is_root() && cred.uid != uid {
+        return false;
+    }
+    //不在文件的属组里，就不能给它设置setgid
+    let mode = if !cred.is_root() && cred.gid != gid { mode & !S_ISGID } else { mode };
+    inode.chmod(mode & MODE_MASK);
+    true
+}
+
+//None表示不改；只有root能改属主，文件的主人只能把属组改成自己的组
+//普通文件换了主人以后setuid和setgid位就去掉，免得变成别人的setuid程序
+pub fn chown(dir: &Arc<Inode>, path: &str, uid: Option<u32>, gid: Option<u32>, cred: Cred) -> bool {
+    let inode = match find_inode(dir, path) {
+        Some(inode) => inode,
+        None => return false,
+    };
+    let (old_uid, old_gid) = inode.owner();
+    let (new_uid, new_gid) = (uid.unwrap_or(old_uid), gid.unwrap_or(old_gid));
+    if !cred.is_root()
+        && (cred.uid != old_uid || new_uid != old_uid || (new_gid != old_gid && new_gid != cred.gid)) {
+        return false;
+    }
+    if (new_uid, new_gid) != (old_uid, old_gid) {
+        inode.chown(new_uid, new_gid);
+        if !inode.is_dir() {
+            inode.chmod(inode.mode() & !(S_ISUID | S_ISGID));
+        }
+    }
+    true
 }
 
 // pub fn count_files(&self, name: &str) -> Option<usize>
@@ -173,28 +567,86 @@ impl File for OSInode {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
     fn inode_id(&self) -> Option<u32> { self.get_my_inode_id() }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn inode(&self) -> Option<Arc<Inode>> { Some(self.inner.lock().inode.clone()) }
+    fn file_type(&self) -> FileType {
+        if self.inner.lock().inode.is_dir() { FileType::Directory } else { FileType::Regular }
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
         let mut inner = self.inner.lock();
-        let mut total_read_size = 0usize;
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
-            if read_size == 0 {
-                break;
-            }
-            inner.offset += read_size;
-            total_read_size += read_size;
-        }
-        total_read_size
+        let read_size = read_inode_at(&inner.inode, inner.offset, buf);
+        inner.offset += read_size;
+        read_size
     }
     fn write(&self, buf: UserBuffer) -> usize {
         let mut inner = self.inner.lock();
-        let mut total_write_size = 0usize;
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
-            inner.offset += write_size;
-            total_write_size += write_size;
+        if self.append {
+            inner.offset = inner.inode.size() as usize;
+        }
+        let write_size = write_inode_at(&inner.inode, inner.offset, buf);
+        inner.offset += write_size;
+        write_size
+    }
+    //可以seek到文件末尾后面，之后写的时候中间空出来的部分读出来是0
+    fn seek(&self, offset: isize, whence: usize) -> Option<usize> {
+        let mut inner = self.inner.lock();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => inner.offset as isize,
+            SEEK_END => inner.inode.size() as isize,
+            _ => return None,
+        };
+        let new_offset = base.checked_add(offset)?;
+        if new_offset < 0 || new_offset as usize > MAX_FILE_SIZE {
+            return None;
+        }
+        inner.offset = new_offset as usize;
+        Some(inner.offset)
+    }
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> Option<usize> {
+        let inner = self.inner.lock();
+        Some(read_inode_at(&inner.inode, offset, buf))
+    }
+    //和Linux一样，APPEND打开的文件pwrite也是写到末尾，offset不起作用
+    //一个字节都写不进去（文件到了最大大小或者磁盘满了）返回None
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> Option<usize> {
+        let inner = self.inner.lock();
+        let offset = if self.append { inner.inode.size() as usize } else { offset };
+        let len = buf.len();
+        match write_inode_at(&inner.inode, offset, buf) {
+            0 if len > 0 => None,
+            write_size => Some(write_size),
+        }
+    }
+}
+
+fn read_inode_at(inode: &Arc<Inode>, mut offset: usize, mut buf: UserBuffer) -> usize {
+    let mut total_read_size = 0usize;
+    for slice in buf.buffers.iter_mut() {
+        let read_size = inode.read_at(offset, *slice);
+        if read_size == 0 {
+            break;
+        }
+        offset += read_size;
+        total_read_size += read_size;
+    }
+    total_read_size
+}
+
+//写到MAX_FILE_SIZE或者磁盘满了就停下来，返回实际写了多少
+fn write_inode_at(inode: &Arc<Inode>, mut offset: usize, buf: UserBuffer) -> usize {
+    let mut total_write_size = 0usize;
+    for slice in buf.buffers.iter() {
+        let room = MAX_FILE_SIZE.saturating_sub(offset);
+        if room == 0 {
+            break;
+        }
+        let slice = &slice[..slice.len().min(room)];
+        let write_size = inode.write_at(offset, slice);
+        offset += write_size;
+        total_write_size += write_size;
+        if write_size < slice.len() {
+            break;
         }
-        total_write_size
     }
+    total_write_size
 }
\ No newline at end of file
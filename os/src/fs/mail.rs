@@ -1,7 +1,8 @@
 //根据测例可以知道，事实上mail要做的事情就是从缓冲区读写，然后存到进程的mail缓冲里面
 //为了实现进程之间通信，还是离不开用户程序自己的控制呀
 use alloc::collections::VecDeque;
-use super::File;
+use super::{File, FileType};
+use easy_fs::Inode;
 use alloc::sync::{Arc, Weak};
 use spin::Mutex;
 use crate::mm::{
@@ -196,6 +197,11 @@ impl File for MPipe {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
     fn inode_id(&self) -> Option<u32> { None }
+    fn inode(&self) -> Option<Arc<Inode>> { None }
+    fn file_type(&self) -> FileType { FileType::Fifo }
+    fn seek(&self, _offset: isize, _whence: usize) -> Option<usize> { None }
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
     fn read(&self, buf: UserBuffer) -> usize {
         assert_eq!(self.readable, true);
         let mut buf_iter = buf.into_iter();
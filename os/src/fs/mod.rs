@@ -4,21 +4,50 @@ mod inode;
 mod mail;
 
 use crate::mm::UserBuffer;
+use easy_fs::Inode;
+use alloc::sync::Arc;
 
 pub trait File : Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn inode_id(&self) -> Option<u32>;
+    /// 只有磁盘上的文件才有inode，目录fd要靠它来解析相对路径
+    fn inode(&self) -> Option<Arc<Inode>>;
+    /// fstat 要用的文件类型
+    fn file_type(&self) -> FileType;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// 移动读写位置，返回新的位置；管道、邮箱和标准输入输出不能 seek，返回 None
+    fn seek(&self, offset: isize, whence: usize) -> Option<usize>;
+    /// 从 offset 开始读写，不改变读写位置；不能 seek 的文件返回 None
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> Option<usize>;
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> Option<usize>;
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FileType {
+    Regular,
+    Directory,
+    /// 管道和邮箱
+    Fifo,
+    /// 标准输入输出
+    CharDevice,
+    Symlink,
+}
+
+/// seek 的 whence
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
 pub use pipe::{Pipe, make_pipe};
 pub use stdio::{Stdin, Stdout};
 pub use inode::{
-    OSInode, open_file, OpenFlags, list_apps,
+    OSInode, open_file, open_file_at, OpenFlags, list_apps,
+    ROOT_INODE, find_inode, find_inode_nofollow, join_path, mkdir, rmdir, rename,
+    symlink, open_exec, open_dir, chmod, chown, Cred,
     get_inode_id,
     create_linker,delete_linker,count_files,
-    count_files_from_id,
+    count_files_from_id, pin_inode, is_pinned,
 };
 pub use mail::{Mail,MailBox,MPipe,make_mpipe};
@@ -1,4 +1,5 @@
-use super::File;
+use super::{File, FileType};
+use easy_fs::Inode;
 use alloc::sync::{Arc, Weak};
 use spin::Mutex;
 use crate::mm::{
@@ -123,6 +124,11 @@ impl File for Pipe {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
     fn inode_id(&self) -> Option<u32> { None }
+    fn inode(&self) -> Option<Arc<Inode>> { None }
+    fn file_type(&self) -> FileType { FileType::Fifo }
+    fn seek(&self, _offset: isize, _whence: usize) -> Option<usize> { None }
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
     fn read(&self, buf: UserBuffer) -> usize {
         assert_eq!(self.readable(), true);
         let mut buf_iter = buf.into_iter();
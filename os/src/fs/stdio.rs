@@ -1,4 +1,6 @@
-use super::File;
+use super::{File, FileType};
+use easy_fs::Inode;
+use alloc::sync::Arc;
 use crate::mm::{UserBuffer};
 use crate::sbi::console_getchar;
 use crate::task::suspend_current_and_run_next;
@@ -11,6 +13,11 @@ impl File for Stdin {
     fn readable(&self) -> bool { true }
     fn writable(&self) -> bool { false }
     fn inode_id(&self) -> Option<u32> { None }
+    fn inode(&self) -> Option<Arc<Inode>> { None }
+    fn file_type(&self) -> FileType { FileType::CharDevice }
+    fn seek(&self, _offset: isize, _whence: usize) -> Option<usize> { None }
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
     fn read(&self, mut user_buf: UserBuffer) -> usize {
         assert_eq!(user_buf.len(), 1);
         // busy loop
@@ -37,6 +44,11 @@ impl File for Stdout {
     fn readable(&self) -> bool { false }
     fn writable(&self) -> bool { true }
     fn inode_id(&self) -> Option<u32> { None }
+    fn inode(&self) -> Option<Arc<Inode>> { None }
+    fn file_type(&self) -> FileType { FileType::CharDevice }
+    fn seek(&self, _offset: isize, _whence: usize) -> Option<usize> { None }
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> Option<usize> { None }
     fn read(&self, _user_buf: UserBuffer) -> usize{
         panic!("Cannot read from stdout!");
     }
@@ -1,9 +1,11 @@
 use super::{PageTable, PageTableEntry, PTEFlags};
 use super::{VirtPageNum, VirtAddr, PhysPageNum, PhysAddr};
-use super::{FrameTracker, frame_alloc, frame_left};
+use super::{FrameTracker, SwapSlot, frame_alloc, frame_left};
+use super::{SharedPages, MappedFile, read_file_page};
 use super::{VPNRange, StepByOne};
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use alloc::string::String;
 use riscv::register::satp;
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -15,6 +17,9 @@ use crate::config::{
     TRAMPOLINE,
     TRAP_CONTEXT,
     USER_STACK_SIZE,
+    SWAP_RESERVE_FRAMES,
+    MMAP_BASE,
+    USER_SPACE_END,
     MMIO,
 };
 
@@ -47,16 +52,19 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    //时钟置换算法的指针，下次从这个虚拟页号开始找要换出的页面
+    clock_hand: VirtPageNum,
 }
 
 fn convert_usize_to_permission(port: usize)->Option<MapPermission>{
     match port{
         1 => Some(MapPermission::R),
-        2 => Some(MapPermission::W),
+        //RISC-V的页表不允许只写不读，有W就带上R
+        2 => Some(MapPermission::R | MapPermission::W),
         3 => Some(MapPermission::R | MapPermission::W),
         4 => Some(MapPermission::X),
         5 => Some(MapPermission::X | MapPermission::R),
-        6 => Some(MapPermission::X | MapPermission::W),
+        6 => Some(MapPermission::X | MapPermission::W | MapPermission::R),
         7 => Some(MapPermission::X | MapPermission::W | MapPermission::R),
         _ => None
     }
@@ -67,6 +75,7 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            clock_hand: VirtPageNum(0),
         }
     }
     pub fn token(&self) -> usize {
@@ -97,6 +106,23 @@ impl MemorySet {
         }
         self.areas.push(map_area);
     }
+    //只记下这个区域，不分配物理页帧，等用户访问的时候在缺页异常里再分配
+    fn push_lazy(&mut self, map_area: MapArea) {
+        self.areas.push(map_area);
+    }
+    //[start, end)和已有的某个区域有重叠
+    fn overlaps(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end && start < area.vpn_range.get_end()
+        })
+    }
+    //[start, end)可以拿来做新的映射：不和已有的区域重叠，页表里也没有映射。
+    //页面是用到才分配的，光查页表查不出来；跳板不在areas里，光查区域也不够
+    fn range_free(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        !self.overlaps(start, end) && (start.0..end.0).all(|vpn| {
+            self.page_table.translate(VirtPageNum(vpn)).map_or(true, |pte| !pte.is_valid())
+        })
+    }
     /// Mention that trampoline is not collected by areas.
     fn map_trampoline(&mut self) {
         self.page_table.map(
@@ -203,12 +229,13 @@ impl MemorySet {
         // guard page
         user_stack_bottom += PAGE_SIZE;
         let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(MapArea::new(
+        //用户栈用到哪一页才分配哪一页
+        memory_set.push_lazy(MapArea::new(
             user_stack_bottom.into(),
             user_stack_top.into(),
             MapType::Framed,
             MapPermission::R | MapPermission::W | MapPermission::U,
-        ), None);
+        ));
         // map TrapContext
         memory_set.push(MapArea::new(
             TRAP_CONTEXT.into(),
@@ -218,23 +245,31 @@ impl MemorySet {
         ), None);
         (memory_set, user_stack_top, elf.header.pt2.entry_point() as usize)
     }
-    //
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    /// fork用：用户可以访问的Framed区域不复制数据，父子进程共享物理页帧并且都去掉W，
+    /// 谁先写谁在缺页异常里复制一份（见handle_page_fault）。
+    /// TrapContext这种内核直接按物理页号去写的区域还是当场复制。
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         //先创建一个空的地址空间
         let mut memory_set = Self::new_bare();
         // map trampoline
         //映射跳转页面（？？？其实上一个实验我就没有太理解什么是跳板页面，不过看起来暂时不是非常影响哦
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
-        for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
+        for area in user_space.areas.iter_mut() {
+            let mut new_area = MapArea::from_another(area);
+            if area.can_share() {
+                //不分配新的物理页帧，两边的页表项指向同一个页帧
+                new_area.share_frames(area, &mut user_space.page_table, &mut memory_set.page_table);
+                memory_set.areas.push(new_area);
+                continue;
+            }
             //先把别人的area复制过来
             //放进memory_set里面
             //注意，这里push进去的时候其实就调用了area的map，就已经分配了物理页帧
             memory_set.push(new_area, None);
             // copy data from another space
             for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                let src_ppn = user_space.page_table.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
                 dst_ppn.get_bytes_array().copy_from_slice(src_ppn.get_bytes_array());
                 //所以这里就是可以直接复制数据的！
@@ -242,6 +277,114 @@ impl MemorySet {
         }
         memory_set
     }
+    /// 处理用户态的缺页异常，va是出错的地址，access是这次访问需要的权限（R/W/X之一）。
+    /// 处理好了返回true，可以回到用户态重新执行那条指令；返回false说明是真的非法访问
+    pub fn handle_page_fault(&mut self, va: VirtAddr, access: MapPermission) -> bool {
+        let vpn = va.floor();
+        let mut end_vpn = vpn;
+        end_vpn.step();
+        self.fault_one(vpn, access, VPNRange::new(vpn, end_vpn))
+    }
+    //对[start, start + len)里的每一页都当作用户做了一次access访问。
+    //整个范围在处理完之前都不能被换出去，内核接下来要直接访问这些页面
+    fn fault_in(&mut self, start: usize, len: usize, access: MapPermission) {
+        if len == 0 {
+            return;
+        }
+        let start_vpn = VirtAddr::from(start).floor();
+        let end_vpn = VirtAddr::from(start + len).ceil();
+        let range = VPNRange::new(start_vpn, end_vpn);
+        for vpn in range {
+            self.fault_one(vpn, access, range);
+        }
+    }
+    //pinned里的页面不会被换出去
+    fn fault_one(&mut self, vpn: VirtPageNum, access: MapPermission, pinned: VPNRange) -> bool {
+        //物理页帧快用完了就先把自己的页面换出去几页，缺页处理和建页表都还要用
+        while frame_left() < SWAP_RESERVE_FRAMES && self.swap_out_one(pinned) {}
+        let page_table = &mut self.page_table;
+        match self.areas.iter_mut().find(|area| area.contains(vpn)) {
+            Some(area) => area.handle_fault(page_table, vpn, access),
+            None => false,
+        }
+    }
+    /// 时钟置换：从clock_hand开始转，A为1的清掉给第二次机会，A为0的换出去。
+    /// 只换只有自己在用的用户页面，和别的进程共享的页帧换出去也腾不出空间。
+    /// 一个能换的都没有返回false
+    fn swap_out_one(&mut self, pinned: VPNRange) -> bool {
+        let pinned_start = pinned.get_start();
+        let pinned_end = pinned.get_end();
+        let mut candidates: Vec<VirtPageNum> = self.areas.iter()
+            .filter(|area| area.can_share())
+            .flat_map(|area| area.data_frames.iter())
+            .filter(|(vpn, frame)| {
+                Arc::strong_count(frame) == 1 && (**vpn < pinned_start || pinned_end <= **vpn)
+            })
+            .map(|(vpn, _)| *vpn)
+            .collect();
+        if candidates.is_empty() {
+            return false;
+        }
+        candidates.sort();
+        let len = candidates.len();
+        let start = candidates.iter().position(|vpn| *vpn >= self.clock_hand).unwrap_or(0);
+        //转两圈，第一圈清掉的A第二圈就是0了
+        for i in 0..2 * len {
+            let vpn = candidates[(start + i) % len];
+            if self.page_table.translate(vpn).unwrap().accessed() {
+                self.page_table.clear_accessed(vpn);
+                continue;
+            }
+            let page_table = &mut self.page_table;
+            let area = self.areas.iter_mut().find(|area| area.contains(vpn)).unwrap();
+            if area.swap_out(page_table, vpn) {
+                self.clock_hand = vpn;
+                self.clock_hand.step();
+                return true;
+            }
+        }
+        false
+    }
+    /// 内核要往用户的[start, start + len)里面写数据之前调用。
+    /// 内核是按物理地址直接写的，不会触发缺页异常，所以要先把还没分配的页面分配好、
+    /// 把共享的页面复制出来
+    pub fn make_writable(&mut self, start: usize, len: usize) {
+        self.fault_in(start, len, MapPermission::W);
+    }
+    /// 内核要读用户的[start, start + len)之前调用，把还没分配的页面分配好
+    pub fn make_present(&mut self, start: usize, len: usize) {
+        self.fault_in(start, len, MapPermission::R);
+    }
+    /// 读用户的以\0结尾的字符串。每读到新的一页先把它弄进来，
+    /// 字符串比空闲的物理页帧还长也没关系；碰到不能访问的地址返回None
+    pub fn translated_str(&mut self, ptr: usize) -> Option<String> {
+        let mut string = String::new();
+        let mut va = ptr;
+        loop {
+            if va >= USER_SPACE_END {
+                return None;
+            }
+            if va == ptr || va % PAGE_SIZE == 0 {
+                self.make_present(va, 1);
+            }
+            let ch: u8 = *self.page_table.translate_va(VirtAddr::from(va))?.get_mut();
+            if ch == 0 {
+                break;
+            }
+            string.push(ch as char);
+            va += 1;
+        }
+        Some(string)
+    }
+    /// 读用户的一个T，先把它所在的页面弄进来，地址不对返回None
+    pub fn translated_ref<T>(&mut self, ptr: usize) -> Option<&'static T> {
+        match ptr.checked_add(core::mem::size_of::<T>()) {
+            Some(end) if end <= USER_SPACE_END => {}
+            _ => return None,
+        }
+        self.make_present(ptr, core::mem::size_of::<T>());
+        Some(self.page_table.translate_va(VirtAddr::from(ptr))?.get_ref())
+    }
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -267,17 +410,74 @@ impl MemorySet {
         return None;
     }
 
-    pub fn unmap_the_chosen_area(&mut self,range: VPNRange)->isize{
-        for area in &mut self.areas{
-            let size = area.unmap_the_chosen_area(&mut self.page_table,range) as isize;
-            if size != -1 {
-                return size;
+    //[start, end)里的每一页是不是都在用户的区域里
+    fn covered_by_user_areas(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        let mut ranges: Vec<(VirtPageNum, VirtPageNum)> = self.areas.iter()
+            .filter(|area| area.can_share())
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            .filter(|(area_start, area_end)| *area_start < end && start < *area_end)
+            .collect();
+        ranges.sort();
+        let mut next = start;
+        for (area_start, area_end) in ranges {
+            if area_start > next {
+                return false;
+            }
+            next = next.max(area_end);
+        }
+        next >= end
+    }
+
+    //vpn落在某个区域中间的话从这里切成两个区域，后半段放到最后
+    fn split_at(&mut self, vpn: VirtPageNum) {
+        if let Some(area) = self.areas.iter_mut().find(|area| {
+            area.vpn_range.get_start() < vpn && vpn < area.vpn_range.get_end()
+        }) {
+            let tail = area.split_off(vpn);
+            self.areas.push(tail);
+        }
+    }
+
+    //把紧挨着、权限和页面来源都一样的区域合成一个，免得mprotect改来改去区域越来越碎
+    fn merge_areas(&mut self) {
+        self.areas.sort_by_key(|area| area.vpn_range.get_start());
+        let mut merged: Vec<MapArea> = Vec::new();
+        for area in self.areas.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.can_merge(&area) => last.merge(area),
+                _ => merged.push(area),
+            }
+        }
+        self.areas = merged;
+    }
+
+    //start为0的时候内核来挑地址：从MMAP_BASE往上找第一个放得下pages页的空隙
+    fn find_free_range(&self, pages: usize) -> Option<VirtPageNum> {
+        let mut ranges: Vec<(VirtPageNum, VirtPageNum)> = self.areas.iter()
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            .collect();
+        ranges.sort();
+        let mut start = VirtAddr::from(MMAP_BASE).floor();
+        for (area_start, area_end) in ranges {
+            if area_end <= start {
+                continue;
+            }
+            if area_start.0 >= start.0 + pages {
+                break;
             }
+            start = area_end;
+        }
+        if start.0 + pages > VirtAddr::from(USER_SPACE_END).floor().0 {
+            return None;
         }
-        return -1 as isize;
+        Some(start)
     }
 
-    pub fn mmap(&mut self,start: usize, len: usize, port: usize) -> isize{
+    /// fixed为false的时候start只是个提示：为0、超出用户地址空间（USER_SPACE_END）
+    /// 或者和已有的映射重叠，就由内核另外挑地址。
+    /// fixed为true的时候一定映射在start，原来在[start, start + len)里的映射先去掉，超出范围就失败。
+    /// backing决定页面从哪来，pgoff是映射开头对应的文件页号。成功返回映射的起始地址
+    pub fn mmap(&mut self,start: usize, len: usize, port: usize, backing: MapBacking, pgoff: usize, fixed: bool) -> isize{
         //要检查的内容：
         //1. 物理内存还够用吗
         //2. 这个地址范围内是不是有哪些已经被映射过了
@@ -287,18 +487,10 @@ impl MemorySet {
         }
         if len == 0 {
             // warn!("in mmap...number=0");
-            return 0 as isize;
-        }
-        let number = ((len - 1 + PAGE_SIZE) /PAGE_SIZE )as usize;
-        //向上取整,表示会用到几个page
-    
-        //以防万一，再检查一遍读写权限问题
-        if (port & !0x7 != 0)||(port & 0x7 == 0) {
             return -1 as isize;
         }
-    
-        //检查一下现在还有几个page，是不是够用.done
-        if frame_left() < number {
+        //以防万一，再检查一遍读写权限问题。MAP_FIXED会先去掉原来的映射，参数要在那之前就检查完
+        if (port & !0x7 != 0)||(port & 0x7 == 0) {
             return -1 as isize;
         }
     
@@ -308,29 +500,48 @@ impl MemorySet {
             None => return -1 as isize,
         }
 
+        let number = ((len - 1 + PAGE_SIZE) /PAGE_SIZE )as usize;
+        //向上取整,表示会用到几个page
+        //start + len不能溢出，也不能超出用户地址空间
+        let in_bounds = start != 0 && match start.checked_add(len) {
+            Some(end) => end <= USER_SPACE_END,
+            None => false,
+        };
+        let start = if fixed {
+            if !in_bounds {
+                return -1 as isize;
+            }
+            //和munmap一样，只盖住区域的一部分的话先切开
+            self.unmap_range(VirtAddr::from(start).floor(), VirtAddr::from(start + len).ceil());
+            start
+        } else if in_bounds && self.range_free(VirtAddr::from(start).floor(), VirtAddr::from(start + len).ceil()) {
+            start
+        } else {
+            match self.find_free_range(number) {
+                Some(vpn) => VirtAddr::from(vpn).into(),
+                None => return -1 as isize,
+            }
+        };
+
         //这个地址范围是不是有人已经映射过了？
-        //根据代码，调用translate检查即可
         //mmap给分配的空间都是在用户态下使用的，因此可以给U权限哦
         let mut area = MapArea::new((start).into(),
                                 (start+len).into(),
                                 MapType::Framed,
                                 permission.unwrap() | MapPermission::U);
+        area.backing = backing;
+        area.pgoff = pgoff;
                                 
-        //调用translate，检查是否全部能完成映射
-        if area.not_map_check(&self.page_table)==false {
+        //MAP_FIXED去不掉的映射（不是用户的区域）还在的话也失败
+        if !self.range_free(area.vpn_range.get_start(), area.vpn_range.get_end()) {
             // warn!("[kernel] have mapped!");
             return -1 as isize;
         }
 
-        area.map(&mut self.page_table);
-    
         let size = usize::from(area.vpn_range.get_end()) - usize::from(area.vpn_range.get_start());
-    
-        self.areas.push(area);
-    
-        //以上合法性检查结束之后，可以直接分配。分为2步：
-        //1，申请物理页面（怎么申请？申请完给谁？）
-        // 这里不用手动写，因为在map的代码里面已经调用了分配物理页帧的函数
+
+        //这里不分配物理页面，也就不用管物理内存够不够，访问到的时候再在缺页异常里分配
+        self.push_lazy(area);
     
         //问题：现在的困难在于，每一个不同的进程都会有不同的映射规则。
         //我在这里怎么访问“当前进程下的。。。。”呢，KERNELSPACE好像是一个不同进程下的东西
@@ -338,7 +549,28 @@ impl MemorySet {
         assert_eq!(number, size);
         // debug!("[kernel] in mmap...size alloc is {}",size);
     
-        return (size*PAGE_SIZE) as isize;
+        return start as isize;
+    }
+
+    /// 把[start, start + len)里的共享文件映射写回文件，别的映射不用管。
+    /// 范围里每一页都要在用户的区域里
+    pub fn msync(&mut self, start: usize, len: usize) -> isize {
+        if start % PAGE_SIZE != 0 {
+            return -1 as isize;
+        }
+        let end = match start.checked_add(len) {
+            Some(end) => end,
+            None => return -1 as isize,
+        };
+        let start_vpn = VirtAddr::from(start).floor();
+        let end_vpn = VirtAddr::from(end).ceil();
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return -1 as isize;
+        }
+        for area in self.areas.iter() {
+            area.write_back(start_vpn, end_vpn);
+        }
+        0
     }
 
     /// 接口：fn unmmap(start: usize, len: usize) -> isize
@@ -363,18 +595,84 @@ impl MemorySet {
         let start_vpn: VirtPageNum = start_va.floor();
         let end_vpn: VirtPageNum = end_va.ceil();
 
-        //这是被映射过去的maparea里面的地址范围
-        let range = VPNRange::new(start_vpn, end_vpn);
+        //范围里有没映射过的页面就失败，一页都不动
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return -1 as isize;
+        }
+        self.unmap_range(start_vpn, end_vpn);
+
+        (end_vpn.0 - start_vpn.0) as isize
+    }
+
+    //把[start_vpn, end_vpn)里用户的区域都去掉，共享的文件映射先写回。范围里可以有没映射的页面
+    //只去掉区域的一部分的话先切开，切完以后范围里的区域都整个去掉
+    fn unmap_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        self.split_at(start_vpn);
+        self.split_at(end_vpn);
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            let area = &mut self.areas[idx];
+            if area.can_share() && start_vpn <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end_vpn {
+                area.write_back(start_vpn, end_vpn);
+                area.unmap(&mut self.page_table);
+                //区域也要去掉，不然再访问的时候又会被当成用到才分配的页面
+                self.areas.remove(idx);
+            } else {
+                idx += 1;
+            }
+        }
+        flush_tlb(start_vpn, end_vpn);
+    }
 
-        return self.unmap_the_chosen_area(range);
-        // return -1 as isize;
+    /// 把[start, start + len)的权限改成port，范围里的每一页都要在用户的区域里。
+    /// 只改区域的一部分的话先切开，改完再和旁边权限一样的区域合起来。成功返回0
+    pub fn mprotect(&mut self, start: usize, len: usize, port: usize) -> isize {
+        if start % PAGE_SIZE != 0 {
+            return -1 as isize;
+        }
+        let permission = match convert_usize_to_permission(port) {
+            Some(permission) => permission | MapPermission::U,
+            None => return -1 as isize,
+        };
+        if len == 0 {
+            return 0;
+        }
+        let start_vpn = VirtAddr::from(start).floor();
+        let end_vpn = VirtAddr::from(start + len).ceil();
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return -1 as isize;
+        }
+        //只读打开的文件做的共享映射不能改成可写，写了也写不回去
+        if permission.contains(MapPermission::W) && self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end() && !area.allows_write()
+        }) {
+            return -1 as isize;
+        }
+        self.split_at(start_vpn);
+        self.split_at(end_vpn);
+        let page_table = &mut self.page_table;
+        for area in self.areas.iter_mut().filter(|area| {
+            area.can_share() && start_vpn <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end_vpn
+        }) {
+            area.set_permission(page_table, permission);
+        }
+        self.merge_areas();
+        flush_tlb(start_vpn, end_vpn);
+        0
     }
 
 }
 
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    //fork之后父子进程可能共享同一个页帧，引用计数就是Arc的计数
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    //换出去的页面在交换文件里的位置，fork之后也可能共享。
+    //页面换回来以后只要没写过，位置还留着，再换出去的时候就不用写了
+    swapped: BTreeMap<VirtPageNum, Arc<SwapSlot>>,
+    backing: MapBacking,
+    //区域开头对应文件（或者共享页面）里的第几页
+    pgoff: usize,
     map_type: MapType,
     map_perm: MapPermission,
 }
@@ -391,16 +689,22 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(start_vpn, end_vpn),
             data_frames: BTreeMap::new(),
+            swapped: BTreeMap::new(),
             map_type,
             map_perm,
+            backing: MapBacking::Anonymous,
+            pgoff: 0,
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
+            swapped: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            backing: another.backing.clone(),
+            pgoff: another.pgoff,
         }
     }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
@@ -413,7 +717,7 @@ impl MapArea {
             MapType::Framed => {
                 let frame = frame_alloc().unwrap();
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
@@ -422,7 +726,11 @@ impl MapArea {
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         match self.map_type {
             MapType::Framed => {
-                self.data_frames.remove(&vpn);
+                self.swapped.remove(&vpn);
+                //还没分配过的和换出去的页面页表里也没有，不用unmap
+                if self.data_frames.remove(&vpn).is_none() {
+                    return;
+                }
             }
             _ => {}
         }
@@ -438,6 +746,142 @@ impl MapArea {
             self.unmap_one(page_table, vpn);
         }
     }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    //只有用户能访问的Framed区域可以在fork的时候共享
+    pub fn can_share(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U)
+    }
+    /// 和another共享它所有的物理页帧，两边的页表项都去掉W。
+    /// 原来就不能写的区域共享以后也不会触发写时复制
+    /// 共享映射本来就要互相看到对方写的内容，两边都保留W
+    pub fn share_frames(&mut self, another: &mut MapArea, src_page_table: &mut PageTable, dst_page_table: &mut PageTable) {
+        let shared = self.is_shared();
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if !shared {
+            pte_flags &= !PTEFlags::W;
+        }
+        for (vpn, frame) in another.data_frames.iter() {
+            if !shared {
+                src_page_table.set_flags(*vpn, pte_flags);
+            }
+            dst_page_table.map(*vpn, frame.ppn, pte_flags);
+            self.data_frames.insert(*vpn, frame.clone());
+        }
+        //在内存里的页面可能已经和交换文件里的不一样了，只共享换出去的页面
+        for (vpn, slot) in another.swapped.iter() {
+            if !another.data_frames.contains_key(vpn) {
+                self.swapped.insert(*vpn, slot.clone());
+            }
+        }
+    }
+    /// 处理落在这个区域里的缺页异常：用户没有这个权限返回false，
+    /// 页面还没分配就分配一个清零的页帧，写共享的页面就写时复制
+    pub fn handle_fault(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, access: MapPermission) -> bool {
+        if self.map_type != MapType::Framed || !self.map_perm.contains(access | MapPermission::U) {
+            return false;
+        }
+        if !self.data_frames.contains_key(&vpn) {
+            let frame = match self.load_page(vpn, access) {
+                Some(frame) => frame,
+                None => return false,
+            };
+            page_table.map(vpn, frame.ppn, PTEFlags::from_bits(self.map_perm.bits).unwrap());
+            self.data_frames.insert(vpn, frame);
+            return true;
+        }
+        if self.is_shared() {
+            //共享映射从来不去掉W，不会有写时复制
+            return true;
+        }
+        if access == MapPermission::W {
+            //内核直接写物理页面不会设置D，要写的页面交换文件里的那份就作废了
+            self.swapped.remove(&vpn);
+            return self.copy_on_write(page_table, vpn);
+        }
+        //页面已经在了，是TLB里还留着旧的页表项，回到用户态的时候会刷掉
+        true
+    }
+    //页面第一次访问或者换出去以后再访问，准备好它的内容
+    fn load_page(&mut self, vpn: VirtPageNum, access: MapPermission) -> Option<Arc<FrameTracker>> {
+        let page = self.pgoff + (vpn.0 - self.vpn_range.get_start().0);
+        if let MapBacking::Shared(shared) = &self.backing {
+            return shared.get_or_load(page);
+        }
+        let frame = frame_alloc()?;
+        //换出去过的页面从交换文件读回来，别的进程还要用这个位置的话自己就不留着了
+        if let Some(slot) = self.swapped.get(&vpn) {
+            slot.read_to(frame.ppn);
+            if access == MapPermission::W || Arc::strong_count(slot) > 1 {
+                self.swapped.remove(&vpn);
+            }
+        } else if let MapBacking::File(file) = &self.backing {
+            read_file_page(file, page, frame.ppn);
+        }
+        Some(Arc::new(frame))
+    }
+    pub fn is_shared(&self) -> bool {
+        match self.backing {
+            MapBacking::Shared(_) => true,
+            _ => false,
+        }
+    }
+    /// 共享文件映射在[start, end)里的页面写回文件
+    pub fn write_back(&self, start: VirtPageNum, end: VirtPageNum) {
+        if let MapBacking::Shared(shared) = &self.backing {
+            let start = start.max(self.vpn_range.get_start());
+            let end = end.min(self.vpn_range.get_end());
+            if start < end {
+                let first = self.pgoff + (start.0 - self.vpn_range.get_start().0);
+                shared.write_back(first, first + (end.0 - start.0));
+            }
+        }
+    }
+    /// 把一个只有自己在用的页面换出去：没写过并且交换文件里还留着原来的位置就直接扔掉，
+    /// 否则写到交换文件里。交换文件满了返回false
+    pub fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let frame = self.data_frames.get(&vpn).unwrap();
+        let dirty = page_table.translate(vpn).unwrap().dirty();
+        if dirty || !self.swapped.contains_key(&vpn) {
+            let slot = match SwapSlot::write_from(frame.ppn) {
+                Some(slot) => slot,
+                None => return false,
+            };
+            self.swapped.insert(vpn, Arc::new(slot));
+        }
+        self.data_frames.remove(&vpn);
+        page_table.unmap(vpn);
+        true
+    }
+    /// 写时复制：页帧还有别人在用就复制一份自己的，只剩自己在用就直接把W加回来。
+    /// 区域本身不可写或者页面还没映射返回false
+    pub fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        if !self.map_perm.contains(MapPermission::W) {
+            return false;
+        }
+        let frame = match self.data_frames.get(&vpn) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if page_table.translate(vpn).map_or(false, |pte| pte.writable()) {
+            return true;
+        }
+        if Arc::strong_count(frame) == 1 {
+            page_table.set_flags(vpn, pte_flags);
+            return true;
+        }
+        let new_frame = match frame_alloc() {
+            Some(new_frame) => new_frame,
+            None => return false,
+        };
+        new_frame.ppn.get_bytes_array().copy_from_slice(frame.ppn.get_bytes_array());
+        page_table.unmap(vpn);
+        page_table.map(vpn, new_frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, Arc::new(new_frame));
+        true
+    }
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
@@ -481,41 +925,86 @@ impl MapArea {
         }
         return true;
     }
-    //check if not mapped...
-    pub fn have_mapped_check(&self,page_table: &PageTable)-> bool{
-        self.print_range();
-        for vpn in self.vpn_range{
-            if let Some(pte) = page_table.translate(vpn){
-                if !pte.is_valid(){
-                    // warn!("[kernel] vpn not mapped is {:#x}",usize::from(pte.ppn()));
-                    return false
-                }
-            }
+    /// 从at切成两段，自己留下[start, at)，返回[at, end)。页帧和换出去的位置跟着页面走
+    pub fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        self.vpn_range = VPNRange::new(start, at);
+        MapArea {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            swapped: self.swapped.split_off(&at),
+            backing: self.backing.clone(),
+            pgoff: self.pgoff + (at.0 - start.0),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
         }
-        return true;
     }
-    pub fn match_area_with_vpnrange(&self, range: VPNRange)->bool{
-        if (self.vpn_range.get_start() == range.get_start()) && (self.vpn_range.get_end() == range.get_end()){
-            true
-        }else{
-            false
+    /// next紧挨在自己后面，权限一样，页面也来自同一个地方并且页号接得上
+    pub fn can_merge(&self, next: &MapArea) -> bool {
+        if !self.can_share() || self.vpn_range.get_end() != next.vpn_range.get_start()
+            || self.map_type != next.map_type || self.map_perm != next.map_perm {
+            return false;
+        }
+        let contiguous = self.pgoff + (self.vpn_range.get_end().0 - self.vpn_range.get_start().0) == next.pgoff;
+        match (&self.backing, &next.backing) {
+            (MapBacking::Anonymous, MapBacking::Anonymous) => true,
+            (MapBacking::File(file), MapBacking::File(next_file)) => Arc::ptr_eq(file, next_file) && contiguous,
+            (MapBacking::Shared(shared), MapBacking::Shared(next_shared)) => Arc::ptr_eq(shared, next_shared) && contiguous,
+            _ => false,
         }
     }
-    pub fn unmap_the_chosen_area(&mut self,page_table: &mut PageTable,range: VPNRange)->isize{
-        if self.match_area_with_vpnrange(range){
-            if !self.have_mapped_check(page_table){
-                //如果发现有还没有map过的页表项想要unmap
-                // warn!("[kernel] vpn not mapped yet ");
-                return -1 as isize;
+    /// 把next接到自己后面，要先用can_merge检查过
+    pub fn merge(&mut self, mut next: MapArea) {
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), next.vpn_range.get_end());
+        self.data_frames.append(&mut next.data_frames);
+        self.swapped.append(&mut next.swapped);
+    }
+    //只读打开的文件做的共享映射不能写
+    pub fn allows_write(&self) -> bool {
+        match &self.backing {
+            MapBacking::Shared(shared) => shared.writable(),
+            _ => true,
+        }
+    }
+    /// 改成新的权限，已经在内存里的页面的页表项也跟着改。
+    /// 私有区域里还和别的进程共享的页帧不给W，写的时候照样写时复制
+    pub fn set_permission(&mut self, page_table: &mut PageTable, map_perm: MapPermission) {
+        self.map_perm = map_perm;
+        let pte_flags = PTEFlags::from_bits(map_perm.bits).unwrap();
+        let shared = self.is_shared();
+        for (vpn, frame) in self.data_frames.iter() {
+            if shared || Arc::strong_count(frame) == 1 {
+                page_table.set_flags(*vpn, pte_flags);
+            } else {
+                page_table.set_flags(*vpn, pte_flags & !PTEFlags::W);
             }
-            self.unmap(page_table);
-            let size = usize::from(range.get_end()) - usize::from(range.get_start());
-            return size as isize;
         }
-        return -1 as isize;
     }
 }
 
+//页表项改了以后把这些页面在TLB里的旧表项刷掉。
+//现在在内核地址空间里，没有用ASID，按虚拟地址刷会把所有地址空间里这个地址的表项都刷掉
+fn flush_tlb(start: VirtPageNum, end: VirtPageNum) {
+    for vpn in VPNRange::new(start, end) {
+        let va: usize = VirtAddr::from(vpn).into();
+        unsafe {
+            llvm_asm!("sfence.vma $0, zero" :: "r"(va) :: "volatile");
+        }
+    }
+}
+
+/// Framed区域的页面从哪来
+#[derive(Clone)]
+pub enum MapBacking {
+    /// 匿名的私有页面，第一次访问的时候是0，fork之后写时复制
+    Anonymous,
+    /// MAP_PRIVATE的文件映射，第一次访问从文件读进来，之后和匿名页面一样，改了也不写回
+    File(MappedFile),
+    /// MAP_SHARED的映射，fork之后父子进程共用同一份页面，文件映射在munmap、msync的时候写回
+    Shared(Arc<SharedPages>),
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Identical,
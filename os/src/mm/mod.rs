@@ -3,6 +3,8 @@ mod address;
 mod frame_allocator;
 mod page_table;
 mod memory_set;
+mod swap;
+mod shared;
 
 
 pub use address::{PhysAddr, VirtAddr,VPNRange, PhysPageNum, VirtPageNum, StepByOne};
@@ -10,6 +12,8 @@ pub use address::{PhysAddr, VirtAddr,VPNRange, PhysPageNum, VirtPageNum, StepByO
 //     PAGE_SIZE,
 // };
 
+pub use swap::SwapSlot;
+pub use shared::{SharedPages, MappedFile, read_file_page};
 pub use frame_allocator::{
     FrameTracker, 
     frame_alloc,
@@ -21,15 +25,13 @@ pub use page_table::{
     PTEFlags,
     PageTableEntry,
     translated_byte_buffer,
-    translated_str,
-    translated_ref,
     translated_refmut,
     UserBuffer,
     UserBufferIterator,
     check_byte_buffer_valid,
 };
 
-pub use memory_set::{MemorySet, KERNEL_SPACE, MapPermission};
+pub use memory_set::{MemorySet, KERNEL_SPACE, MapPermission, MapBacking};
 pub use memory_set::{
     remap_test,
     kernel_token,
@@ -9,7 +9,6 @@ use super::{
 };
 use alloc::vec::Vec;
 use alloc::vec;
-use alloc::string::String;
 use bitflags::*;
 
 //物理页的标志
@@ -61,6 +60,12 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
 }
 
 pub struct PageTable {
@@ -148,15 +153,33 @@ impl PageTable {
         return true;
     }
 
+    //只改页表项的标志位，物理页号不变。fork共享页面的时候用来去掉W
+    //硬件设置的A和D保留下来，换出的时候要看
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before setting flags", vpn);
+        let kept = pte.flags() & (PTEFlags::A | PTEFlags::D);
+        *pte = PageTableEntry::new(pte.ppn(), flags | kept | PTEFlags::V);
+    }
+    //时钟置换算法扫过去的时候清掉A
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before clearing A", vpn);
+        *pte = PageTableEntry::new(pte.ppn(), pte.flags() & !PTEFlags::A);
+    }
+
     //
     //如果这个虚拟地址已经被映射了，那么就返回pte，否则返回None
     //所以想要建立新的映射，应该先检查地址范围内返回值是不是None，如果有一个返回值为None，那么就不能用
+    //页面是用到才分配的，叶子页表项可能还是无效的，这种也要返回None
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn)
+            .filter(|pte| pte.is_valid())
             .map(|pte| {pte.clone()})
     }
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
         self.find_pte(va.clone().floor())
+            .filter(|pte| pte.is_valid())
             .map(|pte| {
                 let aligned_pa: PhysAddr = pte.ppn().into();
                 let offset = va.page_offset();
@@ -237,28 +260,6 @@ pub fn check_byte_buffer_valid(token: usize, ptr: *const u8, len: usize) -> bool
     true
 }
 
-/// Load a string from other address spaces into kernel space without an end `\0`.
-//ptr其实是字符串的地址，试试看这个字符串是不是能翻译成String哦
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-    loop {
-        let ch: u8 = *(page_table.translate_va(VirtAddr::from(va)).unwrap().get_mut());
-        if ch == 0 {
-            break;
-        }
-        string.push(ch as char);
-        va += 1;
-    }
-    string
-}
-
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
-    let page_table = PageTable::from_token(token);
-    page_table.translate_va(VirtAddr::from(ptr as usize)).unwrap().get_ref()
-}
-
 pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
     let page_table = PageTable::from_token(token);
     let va = ptr as usize;
@@ -0,0 +1,75 @@
+use super::{FrameTracker, PhysPageNum, frame_alloc};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use spin::Mutex;
+use crate::config::PAGE_SIZE;
+use crate::fs::File;
+
+//文件映射背后的文件。拿着打开的文件而不是inode，
+//这样close和unlink以后映射还在的时候inode不会被回收
+pub type MappedFile = Arc<dyn File + Send + Sync>;
+
+/// MAP_SHARED映射的页面：fork以后父子进程的区域都指向同一个SharedPages，
+/// 谁写了别人都能看到。文件映射的页面号就是文件里的页号，匿名映射从0开始
+pub struct SharedPages {
+    frames: Mutex<BTreeMap<usize, Arc<FrameTracker>>>,
+    file: Option<MappedFile>,
+}
+
+impl SharedPages {
+    pub fn new(file: Option<MappedFile>) -> Self {
+        Self {
+            frames: Mutex::new(BTreeMap::new()),
+            file,
+        }
+    }
+    /// 匿名的或者文件可写的时候才能映射成可写的
+    pub fn writable(&self) -> bool {
+        self.file.as_ref().map_or(true, |file| file.writable())
+    }
+    /// 第page页，还没有的话分配一个，文件映射从文件里读进来
+    pub fn get_or_load(&self, page: usize) -> Option<Arc<FrameTracker>> {
+        let mut frames = self.frames.lock();
+        if let Some(frame) = frames.get(&page) {
+            return Some(frame.clone());
+        }
+        let frame = frame_alloc()?;
+        if let Some(file) = &self.file {
+            read_file_page(file, page, frame.ppn);
+        }
+        let frame = Arc::new(frame);
+        frames.insert(page, frame.clone());
+        Some(frame)
+    }
+    /// 把[start, end)里已经在内存里的页面写回文件，文件末尾后面的部分不写，匿名映射什么都不做
+    pub fn write_back(&self, start: usize, end: usize) {
+        //只读打开的文件映射不可能被改过
+        let file = match &self.file {
+            Some(file) if file.writable() => file,
+            _ => return,
+        };
+        let inode = file.inode().unwrap();
+        let size = inode.size() as usize;
+        for (page, frame) in self.frames.lock().range(start..end) {
+            let offset = page * PAGE_SIZE;
+            if offset >= size {
+                break;
+            }
+            let len = PAGE_SIZE.min(size - offset);
+            inode.write_at(offset, &frame.ppn.get_bytes_array()[..len]);
+        }
+    }
+}
+
+impl Drop for SharedPages {
+    //最后一个映射没了，把改过的内容写回去
+    fn drop(&mut self) {
+        self.write_back(0, usize::MAX);
+    }
+}
+
+/// 把文件的第page页读到物理页面里，文件末尾后面的部分保持是0
+pub fn read_file_page(file: &MappedFile, page: usize, ppn: PhysPageNum) {
+    let inode = file.inode().unwrap();
+    inode.read_at(page * PAGE_SIZE, ppn.get_bytes_array());
+}
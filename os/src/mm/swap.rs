@@ -0,0 +1,91 @@
+use super::PhysPageNum;
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+use spin::Mutex;
+use lazy_static::*;
+use easy_fs::Inode;
+use crate::config::{PAGE_SIZE, SWAP_FILE, SWAP_SIZE};
+use crate::fs::{ROOT_INODE, pin_inode};
+
+//交换文件里的一个页面大小的位置，和FrameTracker一样drop的时候还回去
+pub struct SwapSlot {
+    pub id: usize,
+}
+
+impl SwapSlot {
+    /// 把物理页面的内容写到交换文件里一个新的位置，交换文件满了或者没有交换文件返回None
+    pub fn write_from(ppn: PhysPageNum) -> Option<Self> {
+        let inode = SWAP_INODE.as_ref()?;
+        let id = SWAP_ALLOCATOR.lock().alloc()?;
+        let slot = Self { id };
+        let written = inode.write_at(id * PAGE_SIZE, ppn.get_bytes_array());
+        if written != PAGE_SIZE {
+            return None;
+        }
+        Some(slot)
+    }
+    /// 把这个位置的内容读回物理页面
+    pub fn read_to(&self, ppn: PhysPageNum) {
+        //有slot就说明交换文件是有的
+        let read = SWAP_INODE.as_ref().unwrap().read_at(self.id * PAGE_SIZE, ppn.get_bytes_array());
+        assert_eq!(read, PAGE_SIZE, "swap slot {} is broken", self.id);
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_ALLOCATOR.lock().dealloc(self.id);
+    }
+}
+
+//和StackFrameAllocator一样，[current,end)是还没用过的位置，recycled是还回来的
+struct SwapAllocator {
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl SwapAllocator {
+    fn new(slots: usize) -> Self {
+        Self {
+            current: 0,
+            end: slots,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(id) = self.recycled.pop() {
+            Some(id)
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some(self.current - 1)
+        }
+    }
+    fn dealloc(&mut self, id: usize) {
+        if id >= self.current || self.recycled.contains(&id) {
+            panic!("Swap slot {} has not been allocated!", id);
+        }
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    //第一次换出的时候才去打开交换文件，镜像里没有就建一个，
+    //一开始就扩到SWAP_SIZE，免得换出的时候磁盘才写满。
+    //建不出来或者镜像里空间不够就当作没有交换文件，内存不够的时候直接失败
+    static ref SWAP_INODE: Option<Arc<Inode>> = {
+        let inode = ROOT_INODE.find(SWAP_FILE)
+            .or_else(|| ROOT_INODE.create_as(SWAP_FILE, 0o600, 0, 0))?;
+        if (inode.size() as usize) < SWAP_SIZE && !inode.truncate(SWAP_SIZE as u32) {
+            println!("[kernel] not enough space for the swap file, swapping disabled");
+            return None;
+        }
+        //内核一直往里面写，不能让unlink把数据块回收掉
+        pin_inode(&inode);
+        Some(inode)
+    };
+    static ref SWAP_ALLOCATOR: Mutex<SwapAllocator> =
+        Mutex::new(SwapAllocator::new(SWAP_SIZE / PAGE_SIZE));
+}
@@ -2,119 +2,175 @@
 #[repr(C)]
 #[derive(Debug,Clone,Copy)]
 pub struct Stat {
-    /// 文件所在磁盘驱动器号
+    /// 文件所在磁盘驱动器号，管道和标准输入输出是 0
     pub dev: u64,
     /// inode 文件所在 inode 编号
     pub ino: u64,
-    /// 文件类型
+    /// 文件类型和权限位
     pub mode: StatMode,
     /// 硬链接数量，初始为1
     pub nlink: u32,
+    /// 属主和属组，不是磁盘文件的话是 0
+    pub uid: u32,
+    pub gid: u32,
+    /// 文件大小，不是磁盘文件的话是 0
+    pub size: u64,
+    /// 最后一次访问、修改内容、修改 inode 的时间，开机以来的毫秒数
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
     /// 无需考虑，为了兼容性设计
-    pad: [u64; 7],
+    pad: [u64; 3],
 }
 
 bitflags! {
     pub struct StatMode: u32 {
         const NULL  = 0;
+        /// named pipe
+        const FIFO  = 0o010000;
+        /// character device
+        const CHR   = 0o020000;
         /// directory
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// symbolic link
+        const LNK   = 0o120000;
+        /// 上面几种类型占的位
+        const TYPE  = 0o170000;
+        /// 权限位：setuid、setgid、sticky 和 rwxrwxrwx
+        const PERM  = 0o7777;
     }
 }
 
-//下面的内容就是建立一个key-word的字典
-//1.字典里面存储的是映射关系，字符串到字符串，string到string的
-//2.这个映射关系存储在磁盘上某个文件中，启动OS的时候把这个文件加载出来
-//3.OS关闭的时候写回去即可
-
 use core::any::Any;
 use alloc::sync::Arc;
-use spin::Mutex;
-use lazy_static::*;
-use alloc::string::String;
-use alloc::collections::btree_map::BTreeMap;
 use super::fs::*;
 use crate::fs::{
     make_pipe, OpenFlags, open_file, OSInode,
     create_linker,delete_linker,count_files,
-    get_inode_id,
+    get_inode_id, find_inode, find_inode_nofollow, symlink,
     File, 
+    FileType,
     Stdin, 
     Stdout,
-    count_files_from_id,
 };
 use crate::mm::{
-    translated_str,
-    VirtAddr,
+    translated_refmut,
+    translated_byte_buffer,
+    UserBuffer,
 };
 use crate::task::{
     current_user_token, 
+    current_user_str,
+    current_cred,
     current_task,
-    current_user_v2p,
+    current_user_make_writable,
 };
+use easy_fs::Inode;
 use super::process::sys_getpid;
 
-lazy_static! {
-    pub static ref PATH_MAPPER: Mutex<BTreeMap<String,String>> = Mutex::new(BTreeMap::new());
-}
-
-pub fn put_link(fake_path:String, real_path:String){
-    PATH_MAPPER.lock().insert(fake_path,real_path);
-}
-
-pub fn get_link(fake_path:&String) -> Option<String>{
-    if let Some(str) = PATH_MAPPER.lock().get(fake_path){
-        Some(String::from(str))//这里必须这么写，
-        //因为get方法返回的是临时变量，事实上并不能直接返回给外面的函数
-    }else{
-        None
-    }
-}
-
-pub fn remove_link(fake_path:&String) ->Option<String>{
-    PATH_MAPPER.lock().remove(fake_path)
-}
-
-
 //=============================
 
 //lab7
 pub fn sys_linkat5(olddirfd: isize, oldpath: *const u8, newdirfd: isize, newpath: *const u8, flags: u32) -> isize{
     info!("sys_linkat5...");
-    sys_linkat(oldpath, newpath, flags)
+    sys_linkat(olddirfd, oldpath, newdirfd, newpath, flags)
 }
-//我大概有思路了，大概就是磁盘上分配一个文件专门用来存储映射信息，存储一个dict，如果产生了这样的系统调用
-//就在dic里面添加值，存储把某个路径映射到哪里的信息
-//unlink就是把地址映射信息删除
-//看样子只要存储字符串就可以了
-//照理来说是要写在磁盘上的，但是现在可以暂时先写在内存里面
-//我知道了！只要照抄用户态，就可以知道如何open如何使用。我在这里确实需要一个文件来存储这些映射关系，
-//所以OS或许还需要对用户程序做出一些要求，比如说不允许使用以.开头的文件名，这些文件名限定为系统文件
-//but now, we can ignore the security and use any name we'd like to
-
 
 //事实上，系统调用接口是syscall有5个参数。但是我实在不知道怎么这样实现，所以为了先写完实验
 //可以先按syscall走，userlib里面的接口也改改就可以了
 //但是最后实现实验的时候大概还要再改改
-pub fn sys_linkat(oldpath: *const u8, newpath: *const u8, flags: u32) -> isize{
-    let token = current_user_token();
-    let real_path = translated_str(token, oldpath);
-    let fake_path = translated_str(token, newpath);
+//两个路径各自按照自己的dirfd解析
+pub fn sys_linkat(olddirfd: isize, oldpath: *const u8, newdirfd: isize, newpath: *const u8, _flags: u32) -> isize{
+    let real_path = match current_user_str(oldpath) {
+        Some(real_path) => real_path,
+        None => return -1,
+    };
+    let fake_path = match current_user_str(newpath) {
+        Some(fake_path) => fake_path,
+        None => return -1,
+    };
     info!("[sys_linkat]...real_path:{},fake_path:{}",real_path,fake_path);
-    if let Some(inode) = create_linker(fake_path.as_str(),real_path.as_str()){
-        return -1 as isize;
-    }else{
+    let (old_dir, new_dir) = match (get_dir_inode(olddirfd), get_dir_inode(newdirfd)) {
+        (Some(old_dir), Some(new_dir)) => (old_dir, new_dir),
+        _ => return -1 as isize,
+    };
+    if create_linker(&new_dir, fake_path.as_str(), &old_dir, real_path.as_str(), current_cred()).is_some(){
         return 0 as isize;
+    }else{
+        return -1 as isize;
     }
 }
 
-pub fn sys_unlinkat(dirfd: isize, path: *const u8, flags: u32) -> isize{
+/// 功能：新建一个符号链接 linkpath，内容是 target。
+/// 参数：target 不需要存在，相对的 target 以后从链接所在的目录开始解析；
+///      linkpath 按 newdirfd 解析，newdirfd 可以是 AT_FDCWD。
+/// 返回值：成功返回 0，linkpath 已经存在或者父目录不存在返回 -1。
+/// syscall ID：36
+pub fn sys_symlinkat(target: *const u8, newdirfd: isize, linkpath: *const u8) -> isize {
+    let target = match current_user_str(target) {
+        Some(target) => target,
+        None => return -1,
+    };
+    let linkpath = match current_user_str(linkpath) {
+        Some(linkpath) => linkpath,
+        None => return -1,
+    };
+    info!("[sys_symlinkat]...target:{},linkpath:{}", target, linkpath);
+    let dir = match get_dir_inode(newdirfd) {
+        Some(dir) => dir,
+        None => return -1,
+    };
+    if symlink(&dir, linkpath.as_str(), target.as_str(), current_cred()) { 0 } else { -1 }
+}
+
+/// 功能：读出符号链接 path 的内容。
+/// 参数：path 按 dirfd 解析，最后一级不跟过去；buf 最多写 bufsiz 字节，末尾不补 \0。
+/// 返回值：成功返回写进 buf 的字节数，太长的话会被截断；path 不是符号链接返回 -1。
+/// syscall ID：78
+pub fn sys_readlinkat(dirfd: isize, path: *const u8, buf: *mut u8, bufsiz: usize) -> isize {
     let token = current_user_token();
-    let fake_path = translated_str(token, path);
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let dir = match get_dir_inode(dirfd) {
+        Some(dir) => dir,
+        None => return -1,
+    };
+    let target = match find_inode_nofollow(&dir, path.as_str()).and_then(|inode| inode.read_link()) {
+        Some(target) => target,
+        None => return -1,
+    };
+    let len = target.len().min(bufsiz);
+    current_user_make_writable(buf as usize, len);
+    if let Some(tsf) = translated_byte_buffer(token, buf, len) {
+        for (dst, src) in UserBuffer::new(tsf).into_iter().zip(target.as_bytes()) {
+            unsafe { *dst = *src; }
+        }
+        len as isize
+    } else {
+        -1
+    }
+}
+
+const AT_REMOVEDIR: u32 = 0x200;
+
+pub fn sys_unlinkat(dirfd: isize, path: *const u8, flags: u32) -> isize{
+    if flags & AT_REMOVEDIR != 0 {
+        return sys_rmdir(dirfd, path);
+    }
+    let fake_path = match current_user_str(path) {
+        Some(fake_path) => fake_path,
+        None => return -1,
+    };
     info!("[sys_unlinkat]...");
-    if delete_linker(fake_path.as_str()){
+    let dir = match get_dir_inode(dirfd) {
+        Some(dir) => dir,
+        None => return -1 as isize,
+    };
+    if delete_linker(&dir, fake_path.as_str(), current_cred()){
         return 0 as isize;
     }else{
         return -1 as isize;
@@ -131,80 +187,103 @@ pub fn return_if_file(f: &(dyn Any + Send + Sync)) -> Option<&Arc<OSInode>>{
     }
 }
 
+//easy-fs只有一个盘
+const DISK_DEV: u64 = 1;
+
+//磁盘文件的信息都从inode里拿，其他的只有类型
+fn make_stat(file_type: FileType, inode: Option<&Arc<Inode>>) -> Stat {
+    let mode = match file_type {
+        FileType::Regular => StatMode::FILE,
+        FileType::Directory => StatMode::DIR,
+        FileType::Fifo => StatMode::FIFO,
+        FileType::CharDevice => StatMode::CHR,
+        FileType::Symlink => StatMode::LNK,
+    };
+    let mut stat = Stat {
+        dev: 0,
+        ino: 0,
+        mode,
+        nlink: 1,
+        uid: 0,
+        gid: 0,
+        size: 0,
+        atime: 0,
+        mtime: 0,
+        ctime: 0,
+        pad: [0; 3],
+    };
+    if let Some(inode) = inode {
+        let (atime, mtime, ctime) = inode.times();
+        stat.dev = DISK_DEV;
+        stat.ino = inode.get_my_inode_id().unwrap() as u64;
+        stat.mode |= StatMode::from_bits_truncate(inode.mode() as u32);
+        let (uid, gid) = inode.owner();
+        stat.uid = uid;
+        stat.gid = gid;
+        stat.nlink = inode.nlink();
+        stat.size = inode.size() as u64;
+        stat.atime = atime;
+        stat.mtime = mtime;
+        stat.ctime = ctime;
+    }
+    stat
+}
+
+/// 功能：获取文件 fd 的状态。
+/// 参数：st 是用户态的 Stat 结构体，磁盘文件会填上 inode 编号、类型、硬链接数、大小和时间戳，
+///      管道是 FIFO，标准输入输出是 CHR。
+/// 返回值：成功返回 0，fd 不合法返回 -1。
+/// syscall ID：80
 pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize{
     info!("[sys_fstat]...fd:{:#x},st:{:#x}",fd,st as usize);
-
+    let token = current_user_token();
     let task = current_task().unwrap();
-    //get-data
-    let mut inner = task.acquire_inner_lock();
-
-    //TODO这儿肯定会写出死锁！！！！
-    //也不一定。。。
-    if let Some(inode) = &inner.fd_table[fd]{
-        //判断这里是不是OSInode类型
-        let inode = inode.clone();
-        drop(inner);
-        if let Some(inode_id) = inode.inode_id(){
-            info!("[sys_fstat] inode_id is {}",inode_id);
-            //TODO:exception solve
-            
-            let count = count_files_from_id(inode_id).unwrap();
-
-            //这边drop掉。但是关键是···类型转换的时候会再加一次锁，这样就死锁了······Orz
-
-            //仿佛明白了······因为现在处理系统调用肯定是运行在内核态
-            //但是这里给的是用户态的虚拟地址。那这就确实是有问题的
-            //因此这里大概是真的需要取出物理地址，然后访问物理地址。
-            //因为OS在页表初始化的时候以及建立了物理地址到物理地址的映射，因此直接访问物理地址也是没问题的吧大概
-            let pa = current_user_v2p(VirtAddr::from(st as usize));
-
-            println!("v2p convert end");
-
-            // info!("[sys_fstat]...physics addr is {:#x}",pa_st as usize);
-            match pa {
-            //pub dev: u64,
-            // /// inode 文件所在 inode 编号
-            // pub ino: u64,
-            // /// 文件类型
-            // pub mode: StatMode,
-            // /// 硬链接数量，初始为1
-            // pub nlink: u32,
-                Some(pa_t)=>{
-                    // let pa_ts = usize::from(pa.unwrap()) as *mut TimeVal;
-                    let pa_st = usize::from(pa_t) as *mut Stat;
-                    info!("[sys_fstat]...physics addr is {:#x}",pa_st as usize);
-
-                    unsafe{
-                        match (*pa_st){
-                            Stat => {
-                                (*pa_st).dev = 1 as u64;
-                                (*pa_st).ino = inode_id as u64;
-                                (*pa_st).mode = StatMode::FILE;
-                                (*pa_st).nlink = count as u32;
-                            }
-                            _ => {
-                                warn!("[sys_fstat] NULL");
-                            },
-                        }
-                    }
-                },
-                _ =>{
-                    warn!("[get_time]  NONE");
-                }
-            }
-            debug!("sys_get_time return...");
-            return 0 as isize;
-            info!("[sys_fstat]...");
-            //todo:从文件描述符到Inode或者name
-            //todo：getfiledata如何得到文件类型是文件夹还是普通文件？
-            -1
-        }else{
-            warn!("[sys_fstat] cant't find inode id...");
-            return -1 as isize;
-        }
-    }else{
-        warn!("[sys_fstat] no file in fdtable[fd]...");
-        return -1 as isize;
+    let file = match task.acquire_inner_lock().fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    current_user_make_writable(st as usize, core::mem::size_of::<Stat>());
+    *translated_refmut(token, st) = make_stat(file.file_type(), file.inode().as_ref());
+    0
+}
+
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+
+/// 功能：按路径获取文件的状态，不用先打开它。
+/// 参数：dirfd 为 AT_FDCWD 或者一个打开的目录，相对路径从这里开始解析；
+///      flags 只能是 0 或者 AT_SYMLINK_NOFOLLOW(0x100)，后者在最后一级是符号链接时报告链接本身。
+/// 返回值：成功返回 0，路径不存在或者 flags 不认识返回 -1。
+/// syscall ID：79
+pub fn sys_fstatat(dirfd: isize, path: *const u8, st: *mut Stat, flags: u32) -> isize {
+    if flags & !AT_SYMLINK_NOFOLLOW != 0 {
+        return -1;
     }
-    //这里是避免发生所有权转移
-}
\ No newline at end of file
+    let token = current_user_token();
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let dir = match get_dir_inode(dirfd) {
+        Some(dir) => dir,
+        None => return -1,
+    };
+    let inode = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        find_inode_nofollow(&dir, path.as_str())
+    } else {
+        find_inode(&dir, path.as_str())
+    };
+    let inode = match inode {
+        Some(inode) => inode,
+        None => return -1,
+    };
+    let file_type = if inode.is_dir() {
+        FileType::Directory
+    } else if inode.is_symlink() {
+        FileType::Symlink
+    } else {
+        FileType::Regular
+    };
+    current_user_make_writable(st as usize, core::mem::size_of::<Stat>());
+    *translated_refmut(token, st) = make_stat(file_type, Some(&inode));
+    0
+}
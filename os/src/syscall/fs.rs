@@ -2,19 +2,26 @@ use crate::mm::{
     UserBuffer,
     translated_byte_buffer,
     translated_refmut,
-    translated_str,
     check_byte_buffer_valid,
 };
 use crate::fs::{
-    make_pipe, OpenFlags, open_file,
+    make_pipe, OpenFlags, open_file_at,
+    open_dir, join_path, mkdir, rmdir, rename, chmod, chown, is_pinned,
+    SEEK_SET, SEEK_CUR,
 };
+use easy_fs::{Inode, block_cache_sync_all};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 use crate::task::{
     current_user_token, 
+    current_cred,
     mail_user_token_pid,
 
     current_task,
+    current_user_make_writable,
+    current_user_make_present,
+    current_user_str,
     mail_write_to_pid,
     mail_write_to_me,
     mail_get_from_me,
@@ -29,10 +36,6 @@ use crate::config::{
     MAIL_SIZE,
 };
 
-use super::flinker::{
-    get_link,
-};
-
 /// 代码段 .text 不允许被修改；
 /// 只读数据段 .rodata 不允许被修改，也不允许从它上面取指；
 /// .data/.bss 均允许被读写，但是不允许从它上面取指。
@@ -41,7 +44,7 @@ use super::flinker::{
 /// 参数：`fd` 表示待写入文件的文件描述符；
 ///      `buf` 表示内存中缓冲区的起始地址；
 ///      `len` 表示内存中缓冲区的长度。
-/// 返回值：返回成功写入的长度。
+/// 返回值：返回成功写入的长度；磁盘上的文件一个字节都写不进去（到了最大大小或者磁盘满了）返回 -1。
 /// syscall ID：64
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     debug!("sys_write...fd {}, buf {:#x}, len {}",fd,buf as usize,len);
@@ -61,8 +64,13 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         // file.write(
         //     UserBuffer::new(translated_byte_buffer(token, buf, len))
         // ) as isize
+        current_user_make_present(buf as usize, len);
         if let Some(tsf) = translated_byte_buffer(token, buf, len){
-            file.write(UserBuffer::new(tsf)) as isize
+            let write_size = file.write(UserBuffer::new(tsf));
+            if write_size == 0 && len > 0 && file.inode().is_some() {
+                return -1;
+            }
+            write_size as isize
         }else{
             -1
         }
@@ -87,6 +95,7 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         // release Task lock manually to avoid deadlock
         // 问题：为什么是在这里drop的？
         drop(inner);
+        current_user_make_writable(buf as usize, len);
         if let Some(tsf) = translated_byte_buffer(token, buf, len){
             file.read(UserBuffer::new(tsf)) as isize
         }else{
@@ -97,38 +106,385 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     }
 }
 
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
-    info!("[sys_open]...");
+/// 功能：移动文件 fd 的读写位置。
+/// 参数：whence 为 SEEK_SET(0) 时新位置是 offset，SEEK_CUR(1) 时是当前位置加 offset，
+///      SEEK_END(2) 时是文件大小加 offset。可以移动到文件末尾后面。
+/// 返回值：成功返回新的读写位置，fd 不合法、不能 seek（管道、标准输入输出等）、
+///      whence 不合法或者新位置是负数返回 -1。
+/// syscall ID：62
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    let task = current_task().unwrap();
+    let file = match task.acquire_inner_lock().fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    match file.seek(offset, whence) {
+        Some(new_offset) => new_offset as isize,
+        None => -1,
+    }
+}
+
+/// 功能：从文件 fd 的 offset 处读出最多 len 个字节，不改变文件的读写位置。
+/// 返回值：返回读出的长度，fd 不合法、不可读或者不能 seek 返回 -1。
+/// syscall ID：67
+pub fn sys_pread64(fd: usize, buf: *const u8, len: usize, offset: usize) -> isize {
+    let token = current_user_token();
     let task = current_task().unwrap();
+    let file = match task.acquire_inner_lock().fd_table.get(fd) {
+        Some(Some(file)) if file.readable() => file.clone(),
+        _ => return -1,
+    };
+    current_user_make_writable(buf as usize, len);
+    match translated_byte_buffer(token, buf, len)
+        .and_then(|tsf| file.read_at(offset, UserBuffer::new(tsf))) {
+        Some(read_size) => read_size as isize,
+        None => -1,
+    }
+}
+
+/// 功能：把缓冲区里的 len 个字节写到文件 fd 的 offset 处，不改变文件的读写位置。
+/// 返回值：返回写入的长度，fd 不合法、不可写、不能 seek，或者一个字节都写不进去返回 -1。
+/// syscall ID：68
+pub fn sys_pwrite64(fd: usize, buf: *const u8, len: usize, offset: usize) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(
-        path.as_str(),
-        OpenFlags::from_bits(flags).unwrap()
-    ) {
+    let task = current_task().unwrap();
+    let file = match task.acquire_inner_lock().fd_table.get(fd) {
+        Some(Some(file)) if file.writable() => file.clone(),
+        _ => return -1,
+    };
+    current_user_make_present(buf as usize, len);
+    match translated_byte_buffer(token, buf, len)
+        .and_then(|tsf| file.write_at(offset, UserBuffer::new(tsf))) {
+        Some(write_size) => write_size as isize,
+        None => -1,
+    }
+}
+
+/// renameat2 的 flags，RENAME_EXCHANGE 和 RENAME_WHITEOUT 不支持
+const RENAME_NOREPLACE: u32 = 1;
+
+/// 功能：把 oldpath 改名成 newpath，可以挪到别的目录下面，整个操作是原子的。
+/// 参数：两个路径各自按照自己的 dirfd 解析，dirfd 为 AT_FDCWD 或者一个打开的目录；
+///      newpath 已经存在的话会被替换掉：文件只能替换文件，目录只能替换空目录；
+///      flags 只能是 0 或者 RENAME_NOREPLACE(1)，后者要求 newpath 原来不存在。
+/// 返回值：成功返回 0，失败（源不存在、目标不能替换、把目录挪到它自己下面、flags 不认识、
+///      没有权限改两边的目录、挪动或者覆盖交换文件等）返回 -1。
+/// syscall ID：276
+pub fn sys_renameat2(olddirfd: isize, oldpath: *const u8, newdirfd: isize, newpath: *const u8, flags: u32) -> isize {
+    if flags & !RENAME_NOREPLACE != 0 {
+        return -1;
+    }
+    let oldpath = match current_user_str(oldpath) {
+        Some(oldpath) => oldpath,
+        None => return -1,
+    };
+    let newpath = match current_user_str(newpath) {
+        Some(newpath) => newpath,
+        None => return -1,
+    };
+    let (old_dir, new_dir) = match (get_dir_inode(olddirfd), get_dir_inode(newdirfd)) {
+        (Some(old_dir), Some(new_dir)) => (old_dir, new_dir),
+        _ => return -1,
+    };
+    let no_replace = flags & RENAME_NOREPLACE != 0;
+    if rename(&old_dir, oldpath.as_str(), &new_dir, newpath.as_str(), no_replace, current_cred()) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// linux_dirent64 的 d_type
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+/// d_ino、d_off、d_reclen、d_type 加起来的长度，后面紧跟着名字
+const DIRENT64_HEADER: usize = 19;
+
+/// 功能：从打开的目录 fd 里读出目录项，格式和 Linux 的 struct linux_dirent64 一样：
+///      d_ino(u64) d_off(i64) d_reclen(u16) d_type(u8) d_name(以 \0 结尾)，每条按 8 字节对齐。
+///      d_off 是下一个目录项的位置，fd 的读写位置也跟着移过去，下次调用接着往下读。
+/// 参数：buf 和 len 是用户态的缓冲区，能放下几条就放几条。
+/// 返回值：返回写进缓冲区的字节数，已经读完了返回 0；
+///      fd 不是目录或者缓冲区连一条目录项都放不下返回 -1。
+/// syscall ID：61
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let file = match task.acquire_inner_lock().fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    let dir = match file.inode() {
+        Some(inode) if inode.is_dir() => inode,
+        _ => return -1,
+    };
+    let offset = file.seek(0, SEEK_CUR).unwrap();
+    let entries = dir.read_dir_from(offset);
+    let mut data: Vec<u8> = Vec::new();
+    let mut next = offset;
+    for entry in entries.iter() {
+        let reclen = (DIRENT64_HEADER + entry.name.len() + 1 + 7) / 8 * 8;
+        if data.len() + reclen > len {
+            break;
+        }
+        let start = data.len();
+        data.extend_from_slice(&(entry.inode_id as u64).to_ne_bytes());
+        data.extend_from_slice(&(entry.next as i64).to_ne_bytes());
+        data.extend_from_slice(&(reclen as u16).to_ne_bytes());
+        data.push(if entry.is_dir {
+            DT_DIR
+        } else if entry.is_symlink {
+            DT_LNK
+        } else {
+            DT_REG
+        });
+        data.extend_from_slice(entry.name.as_bytes());
+        data.resize(start + reclen, 0);
+        next = entry.next;
+    }
+    if data.is_empty() {
+        return if entries.is_empty() { 0 } else { -1 };
+    }
+    current_user_make_writable(buf as usize, data.len());
+    let buffers = match translated_byte_buffer(token, buf, data.len()) {
+        Some(buffers) => buffers,
+        None => return -1,
+    };
+    let mut copied = 0;
+    for buffer in buffers {
+        buffer.copy_from_slice(&data[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+    file.seek(next as isize, SEEK_SET);
+    data.len() as isize
+}
+
+/// 相对路径从当前工作目录开始解析
+pub const AT_FDCWD: isize = -100;
+
+/// 把 *at 系列系统调用的 dirfd 翻译成对应的目录 inode。
+/// dirfd 为 AT_FDCWD 时返回当前工作目录，否则 dirfd 必须是一个打开的目录。
+pub fn get_dir_inode(dirfd: isize) -> Option<Arc<Inode>> {
+    let task = current_task().unwrap();
+    let inner = task.acquire_inner_lock();
+    if dirfd == AT_FDCWD {
+        return Some(inner.cwd.clone());
+    }
+    if dirfd < 0 || dirfd as usize >= inner.fd_table.len() {
+        return None;
+    }
+    let file = inner.fd_table[dirfd as usize].clone()?;
+    drop(inner);
+    file.inode().filter(|inode| inode.is_dir())
+}
+
+/// 功能：打开或者创建一个文件。
+/// 参数：dirfd 为 AT_FDCWD 或者一个打开的目录，相对路径从这里开始解析；
+///      flags 见 OpenFlags，APPEND 打开的文件每次写都写到末尾，TRUNC 清空已经存在的文件，
+///      CREATE 只在文件不存在的时候新建，CREATE | EXCL 要求文件原来不存在，DIRECTORY 要求打开的是目录，
+///      CLOEXEC 打开的 fd 在 exec 的时候关掉。
+/// 返回值：成功返回新的文件描述符；有不认识的标志、标志组合没有意义（比如 WRONLY | RDWR、
+///      只读的时候 TRUNC）、没有读写权限或者打不开都返回 -1。
+/// 新建的文件属于当前进程的 uid 和 gid，权限是 0o644。
+/// syscall ID：56
+pub fn sys_openat(dirfd: isize, path: *const u8, flags: u32) -> isize {
+    info!("[sys_openat]...");
+    let task = current_task().unwrap();
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    if flags.read_write().is_none() {
+        return -1;
+    }
+    let dir = match get_dir_inode(dirfd) {
+        Some(dir) => dir,
+        None => return -1,
+    };
+    //路径上的符号链接（包括最后一级）都会跟过去
+    if let Some(inode) = open_file_at(&dir, path.as_str(), flags, current_cred()) {
         //inode类型是OSInode，就是一个文件（神奇！）
         let mut inner = task.acquire_inner_lock();
         let fd = inner.alloc_fd();
         inner.fd_table[fd] = Some(inode);
+        if flags.contains(OpenFlags::CLOEXEC) {
+            inner.cloexec_fds.insert(fd);
+        }
         return fd as isize;
     } else {
-        //否则就寻找link的路径,如果能找到且可以打开
-        if let Some(real_path) = get_link(&path){
-            if let Some(inode) = open_file(
-                real_path.as_str(),
-                OpenFlags::from_bits(flags).unwrap()
-            ) {
-                //inode类型是OSInode，就是一个文件（神奇！）
-                let mut inner = task.acquire_inner_lock();
-                let fd = inner.alloc_fd();
-                inner.fd_table[fd] = Some(inode);
-                return fd as isize;
-            }
-        }
         return -1 as isize;
     }
 }
 
+/// 功能：创建一个目录。
+/// 参数：dirfd 为 AT_FDCWD 或者一个打开的目录，相对路径从这里开始解析；
+///      path 表示要创建的目录的路径，父目录必须已经存在；
+///      mode 是新目录的权限位，只看低 12 位，没有 umask。
+/// 返回值：成功返回 0，目录已存在、父目录不存在或者不能写返回 -1。
+/// syscall ID：34
+pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> isize {
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    info!("[sys_mkdirat]...path is {}", path);
+    match get_dir_inode(dirfd) {
+        Some(dir) if mkdir(&dir, path.as_str(), mode as u16, current_cred()) => 0,
+        _ => -1,
+    }
+}
+
+/// 功能：删除一个空目录，由 unlinkat 在 flags 带有 AT_REMOVEDIR 时调用。
+/// 返回值：成功返回 0，目录不存在、不为空或者没有权限删返回 -1。
+pub fn sys_rmdir(dirfd: isize, path: *const u8) -> isize {
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    info!("[sys_rmdir]...path is {}", path);
+    match get_dir_inode(dirfd) {
+        Some(dir) if rmdir(&dir, path.as_str(), current_cred()) => 0,
+        _ => -1,
+    }
+}
+
+/// 功能：修改文件的权限位，对应 Linux 的 fchmodat。
+/// 参数：path 按 dirfd 解析，符号链接会跟过去；mode 只看低 12 位。
+/// 返回值：成功返回 0，文件不存在或者当前进程既不是文件的主人也不是 root 返回 -1。
+/// syscall ID：53
+pub fn sys_chmod(dirfd: isize, path: *const u8, mode: u32) -> isize {
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    match get_dir_inode(dirfd) {
+        Some(dir) if chmod(&dir, path.as_str(), mode as u16, current_cred()) => 0,
+        _ => -1,
+    }
+}
+
+/// 功能：修改文件的属主和属组，对应 Linux 的 fchownat。
+/// 参数：path 按 dirfd 解析，符号链接会跟过去；uid 或者 gid 为 -1 表示不改；
+///      flags 只能是 0。
+/// 返回值：成功返回 0，文件不存在或者没有权限返回 -1。只有 root 能改属主，
+///      文件的主人只能把属组改成自己的 gid。普通文件换了属主或属组以后会去掉 setuid 和 setgid 位。
+/// syscall ID：54
+pub fn sys_chown(dirfd: isize, path: *const u8, uid: u32, gid: u32, flags: u32) -> isize {
+    if flags != 0 {
+        return -1;
+    }
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let uid = if uid == u32::MAX { None } else { Some(uid) };
+    let gid = if gid == u32::MAX { None } else { Some(gid) };
+    match get_dir_inode(dirfd) {
+        Some(dir) if chown(&dir, path.as_str(), uid, gid, current_cred()) => 0,
+        _ => -1,
+    }
+}
+
+/// 功能：切换当前进程的工作目录。
+/// 参数：path 可以是绝对路径，也可以是相对于当前工作目录的路径，".." 按目录里的 ".." 项找。
+/// 返回值：成功返回 0，路径不存在、不是目录或者对目录没有 x 权限返回 -1。
+/// 记下的是目录本身，之后目录被改名或者挪走，相对路径也还是从它开始找；
+/// getcwd 返回的路径只是按 chdir 传进来的路径拼出来的，不会跟着变。
+/// syscall ID：49
+pub fn sys_chdir(path: *const u8) -> isize {
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    info!("[sys_chdir]...path is {}", path);
+    let task = current_task().unwrap();
+    let (cwd, cwd_path) = {
+        let inner = task.acquire_inner_lock();
+        (inner.cwd.clone(), inner.cwd_path.clone())
+    };
+    match open_dir(&cwd, path.as_str(), current_cred()) {
+        Some(dir) => {
+            let mut inner = task.acquire_inner_lock();
+            inner.cwd = dir;
+            inner.cwd_path = join_path(cwd_path.as_str(), path.as_str());
+            0
+        }
+        _ => -1,
+    }
+}
+
+/// 功能：获取当前工作目录的绝对路径。
+/// 参数：buf 表示用户缓冲区的起始地址，len 表示缓冲区的长度。
+/// 返回值：成功返回写入的长度（包括结尾的 \0），缓冲区放不下返回 -1。
+/// syscall ID：17
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let cwd = task.acquire_inner_lock().cwd_path.clone();
+    if cwd.len() + 1 > len {
+        return -1;
+    }
+    current_user_make_writable(buf as usize, cwd.len() + 1);
+    if let Some(tsf) = translated_byte_buffer(token, buf, cwd.len() + 1) {
+        let bytes = cwd.as_bytes().iter().chain(core::iter::once(&0u8));
+        for (dst, src) in UserBuffer::new(tsf).into_iter().zip(bytes) {
+            unsafe { *dst = *src; }
+        }
+        (cwd.len() + 1) as isize
+    } else {
+        -1
+    }
+}
+
+/// 功能：把块缓存里所有改过的块都写回磁盘。
+/// 返回值：总是返回 0。
+/// syscall ID：81
+pub fn sys_sync() -> isize {
+    block_cache_sync_all();
+    0
+}
+
+/// 功能：把文件 fd 的修改写回磁盘。
+/// 参数：fd 表示一个已经打开的磁盘文件。
+/// 返回值：成功返回 0，fd 不合法或者不是磁盘文件返回 -1。
+/// 块缓存不知道哪些块属于哪个文件，所以现在和 sync 一样整个写回。
+/// syscall ID：82
+pub fn sys_fsync(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let is_disk_file = match task.acquire_inner_lock().fd_table.get(fd) {
+        Some(Some(file)) => file.inode().is_some(),
+        _ => false,
+    };
+    if !is_disk_file {
+        return -1;
+    }
+    block_cache_sync_all();
+    0
+}
+
+/// 功能：把文件 fd 的大小改成 length，变小时后面的数据块被释放，变大时多出来的部分读出来是 0。
+/// 参数：fd 必须是以可写方式打开的普通文件，length 是新的大小。
+/// 返回值：成功返回 0，fd 不合法、不可写、是目录或者交换文件，length 超过文件的最大大小，或者空闲块不够返回 -1。
+/// syscall ID：46
+pub fn sys_ftruncate(fd: usize, length: usize) -> isize {
+    let task = current_task().unwrap();
+    let file = match task.acquire_inner_lock().fd_table.get(fd) {
+        Some(Some(file)) if file.writable() => file.clone(),
+        _ => return -1,
+    };
+    match file.inode() {
+        Some(inode) if !inode.is_dir() && !is_pinned(&inode) && length <= u32::MAX as usize => {
+            if inode.truncate(length as u32) { 0 } else { -1 }
+        }
+        _ => -1,
+    }
+}
+
 pub fn sys_close(fd: usize) -> isize {
     let task = current_task().unwrap();
     let mut inner = task.acquire_inner_lock();
@@ -139,6 +495,7 @@ pub fn sys_close(fd: usize) -> isize {
         return -1;
     }
     inner.fd_table[fd].take();
+    inner.cloexec_fds.remove(&fd);
     0
 }
 
@@ -151,6 +508,7 @@ pub fn sys_pipe(pipe: *mut usize) -> isize {
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
+    inner.memory_set.make_writable(pipe as usize, 2 * core::mem::size_of::<usize>());
     *translated_refmut(token, pipe) = read_fd;
     *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
     //总而言之以上两句话的含义是，把read_fd和write_fd都写给用户态啦
@@ -200,6 +558,7 @@ pub fn sys_mail_read(buf: *mut u8, l: usize)->isize{
     }
     //如果地址非法，就不能读了
     let token = current_user_token();
+    current_user_make_writable(buf as usize, len);
     if !check_byte_buffer_valid(token, buf, len){
         warn!("[mail_read] invalid byte buffer address,may fail");
         return -1 as isize;
@@ -262,6 +621,7 @@ pub fn sys_mail_write(pid: usize, buf: *mut u8, l: usize)->isize{
         warn!("[mail_write] len too long,continue");
         len = MAIL_SIZE;
     }
+    current_user_make_present(buf as usize, len);
     
     if (p == pid as isize){
         //先检查地址错误
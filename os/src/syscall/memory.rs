@@ -1,34 +1,122 @@
 use crate::task::{
     mmap,
     munmap,
+    mprotect,
+    msync,
+    current_task,
 };
+use crate::mm::{
+    MapBacking,
+    SharedPages,
+};
+use crate::fs::FileType;
+use alloc::sync::Arc;
 
 use crate::config::{
     PAGE_SIZE,
     MEMORY_MAP_SIZE,
 };
 
-pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize{
-    debug!("sys_mmap...start = {:#x}, len = {}, port = {}...",start,len,port);
+/// mmap 的 prot 和 flags
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+const PROT_EXEC: usize = 4;
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// 功能：把一段内存映射到进程的地址空间里，页面都是用到的时候才分配。
+/// 参数：start 必须按页对齐。不带 MAP_FIXED 的时候 start 只是个提示，为 0、超出用户地址空间
+///      或者和已有的映射重叠就由内核另外挑地址；带 MAP_FIXED 的时候一定映射在 start，
+///      原来整个落在这个范围里的映射会先被去掉（和 munmap 一样），start 为 0、超出用户地址空间
+///      或者有映射只被盖住一部分就失败；
+///      prot 是 PROT_READ、PROT_WRITE、PROT_EXEC 的组合，不能是 0；
+///      flags 里 MAP_SHARED 和 MAP_PRIVATE 必须有且只有一个，可以再加 MAP_ANONYMOUS 和 MAP_FIXED；
+///      不是匿名映射的时候 fd 必须是打开的普通文件，offset 按页对齐。
+///      MAP_SHARED 的映射 fork 以后父子进程共用，文件映射在 munmap、msync 和最后一个映射没了的时候写回，
+///      这时候要写的话 fd 必须可写；MAP_PRIVATE 的文件映射改了也不写回。
+/// 返回值：成功返回映射的起始地址，参数不合法或者没有空的地方返回 -1。
+/// syscall ID：222
+pub fn sys_mmap(start: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize{
+    debug!("sys_mmap...start = {:#x}, len = {}, prot = {}, flags = {:#x}, fd = {}, offset = {}...",
+        start, len, prot, flags, fd as isize, offset);
     //需要做几件事：
     //1.检查数据类型是否合法：
     //- start和页对齐
     //- len不能过大不能过小
-    //- port满足一些要求
-    if start % PAGE_SIZE != 0 {
+    //- prot满足一些要求
+    if start % PAGE_SIZE != 0 || offset % PAGE_SIZE != 0 {
         return -1 as isize;
     }
-    if len == 0 {
-        return 0 as isize;
+    if len == 0 || len > MEMORY_MAP_SIZE {
+        return -1 as isize;
     }
-    if len > MEMORY_MAP_SIZE {
+    if (prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0)||(prot == 0) {
+        return -1 as isize;
+    }
+    if flags & !(MAP_SHARED | MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS) != 0 {
+        return -1 as isize;
+    }
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return -1 as isize,
+    };
+    //2.看页面从哪来
+    let backing = if flags & MAP_ANONYMOUS != 0 {
+        if shared {
+            MapBacking::Shared(Arc::new(SharedPages::new(None)))
+        } else {
+            MapBacking::Anonymous
+        }
+    } else {
+        let task = current_task().unwrap();
+        let file = match task.acquire_inner_lock().fd_table.get(fd) {
+            Some(Some(file)) => file.clone(),
+            _ => return -1 as isize,
+        };
+        if file.file_type() != FileType::Regular || !file.readable() {
+            return -1 as isize;
+        }
+        if shared && prot & PROT_WRITE != 0 && !file.writable() {
+            return -1 as isize;
+        }
+        if shared {
+            MapBacking::Shared(Arc::new(SharedPages::new(Some(file))))
+        } else {
+            MapBacking::File(file)
+        }
+    };
+    let pgoff = if flags & MAP_ANONYMOUS != 0 { 0 } else { offset / PAGE_SIZE };
+    return mmap(start, len, prot, backing, pgoff, flags & MAP_FIXED != 0);
+}
+
+/// 功能：把 [start, start + len) 里 MAP_SHARED 的文件映射写回文件，flags 不区分同步异步。
+/// 返回值：成功返回 0，start 没有按页对齐或者范围里有没映射过的页面返回 -1。
+/// syscall ID：227
+pub fn sys_msync(start: usize, len: usize, _flags: usize) -> isize {
+    debug!("sys_msync...start = {:#x}, len = {}",start,len);
+    if start % PAGE_SIZE != 0 || len > MEMORY_MAP_SIZE {
+        return -1 as isize;
+    }
+    msync(start, len)
+}
+
+/// 功能：把 [start, start + len) 的权限改成 prot，可以只改一个映射的一部分。
+/// 参数：start 按页对齐，prot 和 mmap 的一样不能是 0；范围里每一页都要已经映射过。
+///      只读打开的文件做的 MAP_SHARED 映射不能改成可写。
+/// 返回值：成功返回 0，否则返回 -1，这时候权限一页都没有改。
+/// syscall ID：226
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    debug!("sys_mprotect...start = {:#x}, len = {}, prot = {}",start,len,prot);
+    if start % PAGE_SIZE != 0 || len > MEMORY_MAP_SIZE {
         return -1 as isize;
     }
-    if (port & !0x7 != 0)||(port & 0x7 == 0) {
+    if (prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0)||(prot == 0) {
         return -1 as isize;
     }
-    // return -1 as isize;
-    return mmap(start, len, port);
+    mprotect(start, len, prot)
 }
 
 pub fn sys_munmap(start: usize, len: usize) -> isize{
@@ -1,27 +1,49 @@
+const SYSCALL_GETCWD: usize = 17;
 const SYSCALL_DUP: usize = 24;
-const SYSCALL_OPEN: usize = 56;
+const SYSCALL_OPENAT: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_GETDENTS64: usize = 61;
+const SYSCALL_LSEEK: usize = 62;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PREAD64: usize = 67;
+const SYSCALL_PWRITE64: usize = 68;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_SETUID: usize = 146;
+const SYSCALL_SETGID: usize = 144;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 // const SYSCALL_GETTIMEOFDAY: usize = 169;
 const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MSYNC: usize = 227;
 const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_SPAWN: usize = 400;
 const SYSCALL_MAIL_READ: usize = 401;
 const SYSCALL_MAIL_WRITE: usize = 402;
 //=====================lab7===============================
+const SYSCALL_MKDIRAT: usize = 34;
 const SYSCALL_UNLINKAT: usize = 35;
+const SYSCALL_SYMLINKAT: usize = 36;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_FCHMODAT: usize = 53;
+const SYSCALL_FCHOWNAT: usize = 54;
 const SYSCALL_LINKAT: usize = 37;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_READLINKAT: usize = 78;
+const SYSCALL_FSTATAT: usize = 79;
+const SYSCALL_RENAMEAT2: usize = 276;
 const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
 
 mod fs;
 mod process;
@@ -41,11 +63,13 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
     trace!("in syscall...{},{},{},{}",syscall_id,args[0],args[1],args[2]);
     match syscall_id {
         SYSCALL_DUP=> sys_dup(args[0]),
-        SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
+        SYSCALL_OPENAT => sys_openat(args[0] as isize, args[1] as *const u8, args[2] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
         SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
+        SYSCALL_GETDENTS64 => sys_getdents64(args[0], args[1] as *mut u8, args[2]),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_LSEEK => sys_lseek(args[0], args[1] as isize, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_YIELD => sys_yield(),
 
@@ -56,11 +80,16 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0]),
 
         //lab4
-        SYSCALL_MMAP => sys_mmap(args[0],args[1],args[2]),
         SYSCALL_MUNMAP => sys_munmap(args[0],args[1]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0],args[1],args[2]),
+        SYSCALL_MSYNC => sys_msync(args[0],args[1],args[2]),
 
         //lab5
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_GETUID => sys_getuid(),
+        SYSCALL_GETGID => sys_getgid(),
+        SYSCALL_SETUID => sys_setuid(args[0] as u32),
+        SYSCALL_SETGID => sys_setgid(args[0] as u32),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
@@ -75,6 +104,14 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_FSTAT => sys_fstat(args[0], args[1] as *mut Stat),
         //important: permantly
         SYSCALL_UNLINKAT => sys_unlinkat(args[0] as isize, args[1] as *const u8, args[2] as u32),
+        SYSCALL_SYMLINKAT => sys_symlinkat(args[0] as *const u8, args[1] as isize, args[2] as *const u8),
+        SYSCALL_MKDIRAT => sys_mkdirat(args[0] as isize, args[1] as *const u8, args[2] as u32),
+        SYSCALL_CHDIR => sys_chdir(args[0] as *const u8),
+        SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
+        SYSCALL_FTRUNCATE => sys_ftruncate(args[0], args[1]),
+        SYSCALL_FCHMODAT => sys_chmod(args[0] as isize, args[1] as *const u8, args[2] as u32),
+        SYSCALL_SYNC => sys_sync(),
+        SYSCALL_FSYNC => sys_fsync(args[0]),
         // SYSCALL_LINKAT => sys_linkat(args[0] as *const u8, args[1] as *const u8, args[2]),
 
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
@@ -86,7 +123,21 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
 pub fn syscall5(syscall_id: usize, args: [usize; 5]) -> isize{
     match syscall_id {
         SYSCALL_LINKAT => sys_linkat5(args[0] as isize, args[1] as *const u8, args[2] as isize, args[3] as *const u8, args[4] as u32),
+        SYSCALL_FCHOWNAT => sys_chown(args[0] as isize, args[1] as *const u8, args[2] as u32, args[3] as u32, args[4] as u32),
+        SYSCALL_RENAMEAT2 => sys_renameat2(args[0] as isize, args[1] as *const u8, args[2] as isize, args[3] as *const u8, args[4] as u32),
+        SYSCALL_READLINKAT => sys_readlinkat(args[0] as isize, args[1] as *const u8, args[2] as *mut u8, args[3]),
+        SYSCALL_FSTATAT => sys_fstatat(args[0] as isize, args[1] as *const u8, args[2] as *mut Stat, args[3] as u32),
+        SYSCALL_PREAD64 => sys_pread64(args[0], args[1] as *const u8, args[2], args[3]),
+        SYSCALL_PWRITE64 => sys_pwrite64(args[0], args[1] as *const u8, args[2], args[3]),
         // _ => panic!("Unsupported syscall5_id: {}", syscall_id),
         _ => syscall(syscall_id, [args[0], args[1], args[2]]),
     }
+}
+
+//mmap要6个参数，别的还是交给syscall5
+pub fn syscall6(syscall_id: usize, args: [usize; 6]) -> isize{
+    match syscall_id {
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        _ => syscall5(syscall_id, [args[0], args[1], args[2], args[3], args[4]]),
+    }
 }
\ No newline at end of file
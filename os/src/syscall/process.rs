@@ -3,6 +3,9 @@ use crate::task::{
     exit_current_and_run_next,
     current_task,
     current_user_token,
+    current_user_str,
+    current_user_ref,
+    current_cred,
     add_task,
 
     TaskPriority,
@@ -10,13 +13,16 @@ use crate::task::{
     // current_user_v2p,
 };
 use crate::mm::{
-    translated_str,
     translated_refmut,
-    translated_ref,
 };
 use crate::fs::{
-    open_file,
-    OpenFlags,
+    open_exec,
+    OSInode,
+    ROOT_INODE,
+};
+use super::fs::{
+    get_dir_inode,
+    AT_FDCWD,
 };
 
 use alloc::sync::Arc;
@@ -28,6 +34,16 @@ use crate::config::{
 };
 
 
+//先在当前工作目录下面找，找不到再去根目录找
+//相当于PATH里面只有一个"/"，这样换了目录之后shell也还能运行应用
+//要有执行权限才行
+fn open_app(path: &str) -> Option<Arc<OSInode>> {
+    let cred = current_cred();
+    get_dir_inode(AT_FDCWD)
+        .and_then(|cwd| open_exec(&cwd, path, cred))
+        .or_else(|| open_exec(&ROOT_INODE, path, cred))
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     kernel_println!("[kernel] Application exited with code {}", exit_code);
     exit_current_and_run_next(exit_code);
@@ -43,6 +59,48 @@ pub fn sys_getpid() -> isize {
     current_task().unwrap().pid.0 as isize
 }
 
+/// 功能：获取当前进程的 uid。
+/// 返回值：uid，initproc 是 0（root）。
+/// syscall ID：174
+pub fn sys_getuid() -> isize {
+    current_cred().uid as isize
+}
+
+/// 功能：获取当前进程的 gid。
+/// 返回值：gid。
+/// syscall ID：176
+pub fn sys_getgid() -> isize {
+    current_cred().gid as isize
+}
+
+/// 功能：设置当前进程的 uid，之后 fork、spawn 出来的子进程也是这个 uid。
+/// 参数：uid 是新的 uid；root 可以设成任何值，其他进程只能设成自己现在的 uid。
+/// 返回值：成功返回 0，没有权限返回 -1。
+/// syscall ID：146
+pub fn sys_setuid(uid: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.acquire_inner_lock();
+    if inner.uid != 0 && inner.uid != uid {
+        return -1;
+    }
+    inner.uid = uid;
+    0
+}
+
+/// 功能：设置当前进程的 gid。
+/// 参数：gid 是新的 gid；只有 root 可以换成别的 gid，所以要先 setgid 再 setuid。
+/// 返回值：成功返回 0，没有权限返回 -1。
+/// syscall ID：144
+pub fn sys_setgid(gid: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.acquire_inner_lock();
+    if inner.uid != 0 && inner.gid != gid {
+        return -1;
+    }
+    inner.gid = gid;
+    0
+}
+
 pub fn sys_fork() -> isize {
     let token = current_user_token();
     info!("sys_fork...current user toker is {:#x}",token);
@@ -65,19 +123,27 @@ pub fn sys_fork() -> isize {
 }
 
 pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
     let mut args_vec: Vec<String> = Vec::new();
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
+        let arg_str_ptr = match current_user_ref(args) {
+            Some(arg_str_ptr) => *arg_str_ptr,
+            None => return -1,
+        };
         if arg_str_ptr == 0 {
             break;
         }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+        match current_user_str(arg_str_ptr as *const u8) {
+            Some(arg) => args_vec.push(arg),
+            None => return -1,
+        }
         unsafe { args = args.add(1); }
     }
     info!("sys_exec...path is {}",path.as_str());
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
+    if let Some(app_inode) = open_app(path.as_str()) {
         let all_data = app_inode.read_all();
         let task = current_task().unwrap();
         let argc = args_vec.len();
@@ -98,9 +164,11 @@ pub fn sys_spawn(path: *const u8, mut args: *const usize) -> isize{
     // let path = translated_str(token, path);//总之就是把*const u8翻译成String类型
     // info!("sys_spawn...{}",path.as_str());
     //处理要打开的应用信息
-    let token = current_user_token();
     //path is String
-    let path = translated_str(token, path);
+    let path = match current_user_str(path) {
+        Some(path) => path,
+        None => return -1,
+    };
     let mut args_vec: Vec<String> = Vec::new();
     // spawn暂时不支持添加参数了吧
     // loop {
@@ -112,7 +180,7 @@ pub fn sys_spawn(path: *const u8, mut args: *const usize) -> isize{
     //     unsafe { args = args.add(1); }
     // }
     info!("sys_spawn...path is {}",path.as_str());
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
+    if let Some(app_inode) = open_app(path.as_str()) {
         let all_data = app_inode.read_all();
         let current_task = current_task().unwrap();
         let argc = args_vec.len();
@@ -180,6 +248,7 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // ++++ temporarily hold child lock
         let exit_code = child.acquire_inner_lock().exit_code;
         // ++++ release child PCB lock
+        inner.memory_set.make_writable(exit_code_ptr as usize, core::mem::size_of::<i32>());
         *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
         kernel_println!("find pid have done :{}",found_pid);
         found_pid as isize
@@ -23,6 +23,7 @@ pub use processor::{
     run_tasks,
     current_task,
     current_user_token,
+    current_cred,
     current_trap_cx,
     take_current_task,
     schedule,
@@ -30,7 +31,14 @@ pub use processor::{
     set_priority,
     mmap,
     munmap,
+    mprotect,
+    msync,
     current_user_v2p,
+    current_user_make_writable,
+    current_user_make_present,
+    current_user_str,
+    current_user_ref,
+    handle_page_fault,
     mail_write_to_me,
     mail_get_from_me,
     mail_not_full_me,
@@ -1,5 +1,6 @@
 use super::TaskControlBlock;
 use alloc::sync::Arc;
+use alloc::string::String;
 use core::cell::RefCell;
 use lazy_static::*;
 use super::{
@@ -11,9 +12,12 @@ use super::{
 use crate::mm::{
     VirtAddr,
     PhysAddr,
+    MapPermission,
+    MapBacking,
 };
 use crate::fs::{
     MPipe,
+    Cred,
 };
 use super::__switch;
 use crate::trap::TrapContext;
@@ -95,6 +99,10 @@ pub fn current_user_token() -> usize {
     token
 }
 
+pub fn current_cred() -> Cred {
+    current_task().unwrap().acquire_inner_lock().cred()
+}
+
 pub fn current_trap_cx() -> &'static mut TrapContext {
     current_task().unwrap().acquire_inner_lock().get_trap_cx()
 }
@@ -108,16 +116,60 @@ pub fn set_priority(prio:TaskPriority){
     task.set_priority(prio);
 }
 
-pub fn mmap(start: usize, len: usize, port: usize) -> isize{
+pub fn mmap(start: usize, len: usize, port: usize, backing: MapBacking, pgoff: usize, fixed: bool) -> isize{
     let task = current_task().unwrap();
-    task.mmap(start, len, port)
+    task.mmap(start, len, port, backing, pgoff, fixed)
 }//函数结束自动释放锁
 
+pub fn mprotect(start: usize, len: usize, port: usize) -> isize{
+    let task = current_task().unwrap();
+    task.mprotect(start, len, port)
+}
+
+pub fn msync(start: usize, len: usize) -> isize{
+    let task = current_task().unwrap();
+    task.msync(start, len)
+}
+
 pub fn munmap(start: usize, len: usize) -> isize{
     let task = current_task().unwrap();
     task.munmap(start,len)
 }
 
+//缺页异常的时候调用，能处理（延迟分配、写时复制）就返回true
+pub fn handle_page_fault(va: VirtAddr, access: MapPermission) -> bool {
+    let task = current_task().unwrap();
+    let mut inner = task.acquire_inner_lock();
+    inner.memory_set.handle_page_fault(va, access)
+}
+
+//内核往用户缓冲区写东西之前先调用，把还没分配的页面分配好、fork之后还在共享的页面复制出来
+pub fn current_user_make_writable(start: usize, len: usize) {
+    let task = current_task().unwrap();
+    let mut inner = task.acquire_inner_lock();
+    inner.memory_set.make_writable(start, len);
+}
+
+//内核读用户缓冲区之前先调用，把还没分配的页面分配好
+pub fn current_user_make_present(start: usize, len: usize) {
+    let task = current_task().unwrap();
+    let mut inner = task.acquire_inner_lock();
+    inner.memory_set.make_present(start, len);
+}
+
+//读用户传进来的路径、参数，页面可能还没分配或者被换出去了，地址不对返回None
+pub fn current_user_str(ptr: *const u8) -> Option<String> {
+    let task = current_task().unwrap();
+    let mut inner = task.acquire_inner_lock();
+    inner.memory_set.translated_str(ptr as usize)
+}
+
+pub fn current_user_ref<T>(ptr: *const T) -> Option<&'static T> {
+    let task = current_task().unwrap();
+    let mut inner = task.acquire_inner_lock();
+    inner.memory_set.translated_ref(ptr as usize)
+}
+
 pub fn current_user_v2p(va:VirtAddr)->Option<PhysAddr>{
     let task = current_task().unwrap();
     task.v2p(va)
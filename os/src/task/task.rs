@@ -5,6 +5,7 @@ use crate::mm::{
     VirtAddr,
     translated_refmut,
     PhysAddr,
+    MapBacking,
 };
 use crate::trap::{TrapContext, trap_handler};
 use crate::config::{TRAP_CONTEXT};
@@ -17,6 +18,7 @@ use alloc::sync::{Weak, Arc};
 use alloc::vec;
 use alloc::vec::Vec;
 use alloc::string::String;
+use alloc::collections::BTreeSet;
 use spin::{Mutex, MutexGuard};
 use crate::fs::{
     File, 
@@ -26,7 +28,16 @@ use crate::fs::{
     MailBox,
     MPipe,
     make_mpipe,
+    ROOT_INODE,
+    Cred,
 };
+use easy_fs::Inode;
+
+//把args压到用户栈上最多要占多少字节（包括argv数组和最后对齐用的8字节）
+fn args_stack_size(args: &[String]) -> usize {
+    (args.len() + 2) * core::mem::size_of::<usize>()
+        + args.iter().map(|arg| arg.len() + 1).sum::<usize>()
+}
 
 // #[derive(Copy, Clone, PartialEq)]
 pub struct TaskControlBlock {
@@ -49,6 +60,11 @@ pub struct TaskControlBlockInner {
     pub exit_code: i32,
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
     pub mailbox:MailBox,//add
+    pub cwd: Arc<Inode>,//当前工作目录，它被改名或者挪走以后也还是这个目录
+    pub cwd_path: String,//当前工作目录的绝对路径，只给getcwd用
+    pub cloexec_fds: BTreeSet<usize>,//带O_CLOEXEC打开的fd，exec的时候关掉
+    pub uid: u32,//fork和spawn出来的子进程跟着父进程，exec不变
+    pub gid: u32,
 }
 
 impl TaskControlBlockInner {
@@ -61,6 +77,10 @@ impl TaskControlBlockInner {
     pub fn get_user_token(&self) -> usize {
         self.memory_set.token()
     }
+    //检查文件权限的时候用
+    pub fn cred(&self) -> Cred {
+        Cred { uid: self.uid, gid: self.gid }
+    }
     fn get_status(&self) -> TaskStatus {
         self.task_status
     }
@@ -70,12 +90,22 @@ impl TaskControlBlockInner {
     pub fn alloc_fd(&mut self) -> usize {
         if let Some(fd) = (0..self.fd_table.len())
             .find(|fd| self.fd_table[*fd].is_none()) {
+            //新分到的fd不能继承上一个用这个号的文件的CLOEXEC
+            self.cloexec_fds.remove(&fd);
             fd
         } else {
             self.fd_table.push(None);
             self.fd_table.len() - 1
         }
     }
+    //exec的时候调用，关掉所有带CLOEXEC的fd
+    pub fn close_on_exec(&mut self) {
+        for fd in core::mem::replace(&mut self.cloexec_fds, BTreeSet::new()) {
+            if let Some(file) = self.fd_table.get_mut(fd) {
+                file.take();
+            }
+        }
+    }
 }
 
 impl TaskControlBlock {
@@ -117,6 +147,12 @@ impl TaskControlBlock {
                     Some(Arc::new(Stdout)),
                 ],
                 mailbox: MailBox::new(),
+                cwd: ROOT_INODE.clone(),
+                cwd_path: String::from("/"),
+                cloexec_fds: BTreeSet::new(),
+                //initproc是root
+                uid: 0,
+                gid: 0,
             }),
         };
         // prepare TrapContext in user space
@@ -132,11 +168,14 @@ impl TaskControlBlock {
     }
     pub fn exec(&self, elf_data: &[u8], args: Vec<String>) {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (mut memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
+        //用户栈是用到才分配的，先把要压参数的地方分配好。下面参数压了两遍
+        let args_size = 2 * args_stack_size(&args);
+        memory_set.make_writable(user_sp - args_size, args_size);
         // push arguments on user stack
         user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
         let argv_base = user_sp;
@@ -190,6 +229,7 @@ impl TaskControlBlock {
 
         // **** hold current PCB lock
         let mut inner = self.acquire_inner_lock();
+        inner.close_on_exec();
         // substitute memory_set
         inner.memory_set = memory_set;
         // update trap_cx ppn
@@ -218,7 +258,7 @@ impl TaskControlBlock {
             //复制一份一模一样的用户空间
             //确实······感觉这里复制这一份出来，就是为了变成一个数据结构存起来，好像什么作用也没有。
             //因为真的task被执行的时候，用到的是exec里面取出来的memory_set呀
-            &parent_inner.memory_set
+            &mut parent_inner.memory_set
         );
         //取出复制出来的空间的物理页号
         let trap_cx_ppn = memory_set
@@ -256,6 +296,11 @@ impl TaskControlBlock {
                 exit_code: 0,
                 fd_table: new_fd_table,
                 mailbox: MailBox::new(),//邮箱并不能和父进程共享，不然几个函数之间互相传递信息就是在胡扯了
+                cwd: parent_inner.cwd.clone(),
+                cwd_path: parent_inner.cwd_path.clone(),
+                cloexec_fds: parent_inner.cloexec_fds.clone(),
+                uid: parent_inner.uid,
+                gid: parent_inner.gid,
             }),
         });
         // add child
@@ -275,11 +320,14 @@ impl TaskControlBlock {
     // pub fn spawn(&self, elf_data: &[u8]) -> isize{
     pub fn spawn_from(self: &Arc<TaskControlBlock>, elf_data: &[u8], args: Vec<String>) -> Arc<TaskControlBlock>{
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (mut memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
+        //用户栈是用到才分配的，先把要压参数的地方分配好
+        let args_size = args_stack_size(&args);
+        memory_set.make_writable(user_sp - args_size, args_size);
 
         // [lab7]add
         // push arguments on user stack
@@ -320,11 +368,12 @@ impl TaskControlBlock {
         let mut parent_inner = self.acquire_inner_lock();
         // copy fd table
         let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
+        //spawn出来的进程直接跑新程序，相当于exec过了，CLOEXEC的fd不带过去
+        for (fd, file) in parent_inner.fd_table.iter().enumerate() {
+            if parent_inner.cloexec_fds.contains(&fd) {
                 new_fd_table.push(None);
+            } else {
+                new_fd_table.push(file.clone());
             }
         }
         let task_control_block = Arc::new(TaskControlBlock {
@@ -342,6 +391,11 @@ impl TaskControlBlock {
                 exit_code: 0,
                 fd_table: new_fd_table,
                 mailbox: MailBox::new(),//邮箱并不能和父进程共享，不然几个函数之间互相传递信息就是在胡扯了
+                cwd: parent_inner.cwd.clone(),
+                cwd_path: parent_inner.cwd_path.clone(),
+                cloexec_fds: BTreeSet::new(),
+                uid: parent_inner.uid,
+                gid: parent_inner.gid,
             }),
         });
         // add child
@@ -386,10 +440,22 @@ impl TaskControlBlock {
         // **** release current PCB lock
     }
 
-    pub fn mmap(&self,start: usize, len: usize, port: usize) -> isize{
+    pub fn mmap(&self,start: usize, len: usize, port: usize, backing: MapBacking, pgoff: usize, fixed: bool) -> isize{
+        // **** hold current PCB lock
+        let mut inner = self.acquire_inner_lock();
+        inner.memory_set.mmap(start, len, port, backing, pgoff, fixed) 
+        // **** release current PCB lock
+    }
+    pub fn mprotect(&self,start: usize, len: usize, port: usize) -> isize{
+        // **** hold current PCB lock
+        let mut inner = self.acquire_inner_lock();
+        inner.memory_set.mprotect(start, len, port)
+        // **** release current PCB lock
+    }
+    pub fn msync(&self,start: usize, len: usize) -> isize{
         // **** hold current PCB lock
         let mut inner = self.acquire_inner_lock();
-        inner.memory_set.mmap(start, len, port) 
+        inner.memory_set.msync(start, len)
         // **** release current PCB lock
     }
     pub fn munmap(&self,start: usize, len: usize) -> isize{
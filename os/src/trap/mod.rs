@@ -14,16 +14,18 @@ use riscv::register::{
     sie,
 };
 use crate::syscall::{
-    syscall5,
+    syscall6,
 };
 use crate::task::{
     exit_current_and_run_next,
     suspend_current_and_run_next,
     current_user_token,
     current_trap_cx,
+    handle_page_fault,
     // TASK_MANAGER,
 };
 use crate::timer::set_next_trigger;
+use crate::mm::{VirtAddr, MapPermission};
 use crate::config::{TRAP_CONTEXT, TRAMPOLINE};
 // use crate::timer::set_next_trigger;
 // use crate::timer::{get_time,get_time_ms};
@@ -64,7 +66,7 @@ pub fn trap_handler() -> ! {
             cx.sepc += 4;
             // get system call return value
             // let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
-            let result = syscall5(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14]]);
+            let result = syscall6(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]]);
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
@@ -74,6 +76,14 @@ pub fn trap_handler() -> ! {
         //但是在我们这次实验里面，是直接读的。使用中断&&DMA工作量还是很大的
         //硬件在进入的时候进行中断屏蔽。
         //内核在执行过程中是否允许嵌套中断，取决于内核的实现。
+        //还没分配的页面在这里分配，fork之后共享的页面第一次写在这里复制一份，
+        //处理好了回去重新执行那条指令
+        Trap::Exception(Exception::StorePageFault)
+            if handle_page_fault(VirtAddr::from(stval), MapPermission::W) => {}
+        Trap::Exception(Exception::LoadPageFault)
+            if handle_page_fault(VirtAddr::from(stval), MapPermission::R) => {}
+        Trap::Exception(Exception::InstructionPageFault)
+            if handle_page_fault(VirtAddr::from(stval), MapPermission::X) => {}
         Trap::Exception(Exception::StoreFault) |
         Trap::Exception(Exception::StorePageFault) |
         Trap::Exception(Exception::InstructionFault) |
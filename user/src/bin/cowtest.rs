@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, getpid, pipe, read, wait, write};
+
+/// 测试写时复制的 fork，输出 Test cow OK! 就算正确。
+/// 父进程有一大块数据，fork 很多个子进程，每个子进程只改其中一两页，
+/// 如果 fork 还是整个复制的话物理页帧很快就不够用了。
+
+const PAGES: usize = 64;
+const CHILDREN: usize = 64;
+const PAGE_SIZE: usize = 4096;
+
+static mut DATA: [u8; PAGES * PAGE_SIZE] = [0; PAGES * PAGE_SIZE];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    unsafe {
+        for page in 0..PAGES {
+            DATA[page * PAGE_SIZE] = page as u8;
+        }
+    }
+    for i in 0..CHILDREN {
+        let pid = fork();
+        assert!(pid >= 0);
+        if pid == 0 {
+            let page = i % PAGES;
+            unsafe {
+                // the child still sees the parent's data before writing
+                assert_eq!(DATA[page * PAGE_SIZE], page as u8);
+                DATA[page * PAGE_SIZE] = 0xff;
+                assert_eq!(DATA[page * PAGE_SIZE], 0xff);
+                // other pages are untouched
+                let other = (page + 1) % PAGES;
+                assert_eq!(DATA[other * PAGE_SIZE], other as u8);
+            }
+            exit(0);
+        }
+    }
+    let mut exit_code: i32 = 0;
+    for _ in 0..CHILDREN {
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 0);
+    }
+    assert!(wait(&mut exit_code) < 0);
+    // writes in the children never reach the parent
+    unsafe {
+        for page in 0..PAGES {
+            assert_eq!(DATA[page * PAGE_SIZE], page as u8);
+        }
+    }
+
+    // the kernel writing into a shared page (read into a buffer) must not leak to the other process
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[1]);
+        unsafe {
+            assert_eq!(read(pipe_fd[0], &mut DATA[..5]), 5);
+            assert_eq!(&DATA[..5], b"child");
+        }
+        close(pipe_fd[0]);
+        exit(0);
+    }
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], b"child"), 5);
+    close(pipe_fd[1]);
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    unsafe {
+        assert_eq!(&DATA[..5], &[0u8; 5]);
+    }
+    println!("Test cow OK! pid = {}", getpid());
+    0
+}
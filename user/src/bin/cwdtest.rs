@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, close, getcwd, linkat, mkdir, open, openat, read, rmdir, unlink, write, OpenFlags,
+};
+
+/// 测试工作目录 chdir/getcwd 以及相对路径、dirfd 相对的访问，输出　Test cwd OK! 就算正确。
+
+fn cwd(buf: &mut [u8]) -> &str {
+    let len = getcwd(buf);
+    assert!(len > 0);
+    core::str::from_utf8(&buf[..len as usize - 1]).unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 64];
+    let test_str = "Hello, cwd!";
+    assert_eq!(mkdir("cwd0\0"), 0);
+    assert_eq!(mkdir("cwd0/cwd1\0"), 0);
+
+    assert_eq!(chdir("cwd0/cwd1\0"), 0);
+    assert_eq!(cwd(&mut buf), "/cwd0/cwd1");
+    // buffer too small
+    assert_eq!(getcwd(&mut buf[..4]), -1);
+    // not a directory / not exists
+    assert_eq!(chdir("nodir\0"), -1);
+    assert_eq!(cwd(&mut buf), "/cwd0/cwd1");
+
+    let fd = open("file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, test_str.as_bytes());
+    close(fd as usize);
+    assert_eq!(chdir("file\0"), -1);
+
+    assert_eq!(chdir("..\0"), 0);
+    assert_eq!(cwd(&mut buf), "/cwd0");
+
+    // 通过目录 fd 打开和链接
+    let dirfd = open("cwd1\0", OpenFlags::RDONLY);
+    assert!(dirfd > 0);
+    let fd = openat(dirfd, "file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd as usize, &mut buffer) as usize;
+    close(fd as usize);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+    assert_eq!(linkat(dirfd, "file\0", dirfd, "link\0"), 0);
+    close(dirfd as usize);
+    assert!(open("/cwd0/cwd1/link\0", OpenFlags::RDONLY) > 0);
+
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(cwd(&mut buf), "/");
+    assert_eq!(unlink("cwd0/cwd1/link\0"), 0);
+    assert_eq!(unlink("cwd0/cwd1/file\0"), 0);
+    assert_eq!(rmdir("cwd0/cwd1\0"), 0);
+    assert_eq!(rmdir("cwd0\0"), 0);
+    println!("Test cwd OK!");
+    0
+}
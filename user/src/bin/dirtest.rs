@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, open, read, rmdir, unlink, write, OpenFlags};
+
+/// 测试多级目录的创建、访问和删除，输出　Test dir OK! 就算正确。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let test_str = "Hello, dir!";
+    assert_eq!(mkdir("dir0\0"), 0);
+    assert_eq!(mkdir("dir0\0"), -1);
+    assert_eq!(mkdir("dir0/dir1\0"), 0);
+    // parent does not exist
+    assert_eq!(mkdir("nodir/dir1\0"), -1);
+
+    let fd = open("/dir0/dir1/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    close(fd);
+
+    let fd = open("dir0/./dir1/../dir1/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd, &mut buffer) as usize;
+    close(fd);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+
+    // directories cannot be unlinked or removed while not empty
+    assert_eq!(unlink("dir0/dir1\0"), -1);
+    assert_eq!(rmdir("dir0/dir1\0"), -1);
+    assert_eq!(unlink("dir0/dir1/file\0"), 0);
+    assert_eq!(rmdir("dir0/dir1\0"), 0);
+    assert_eq!(rmdir("dir0\0"), 0);
+    assert!(open("dir0/dir1/file\0", OpenFlags::RDONLY) < 0);
+    println!("Test dir OK!");
+    0
+}
@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, getdents64, lseek, mkdir, open, parse_dirents, rmdir, unlink, OpenFlags, DT_DIR,
+    DT_REG, SEEK_SET,
+};
+
+/// 测试 getdents64 分几次读完一个目录，输出　Test getdents OK! 就算正确。
+
+const FILES: [&str; 5] = ["gd/f0\0", "gd/f1\0", "gd/f2\0", "gd/f3\0", "gd/f4\0"];
+
+fn read_all(fd: usize, buf: &mut [u8]) -> Vec<(String, u8)> {
+    let mut names = Vec::new();
+    loop {
+        let len = getdents64(fd, buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for entry in parse_dirents(&buf[..len as usize]) {
+            names.push((entry.name, entry.d_type));
+        }
+    }
+    names
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("gd\0"), 0);
+    assert_eq!(mkdir("gd/sub\0"), 0);
+    for file in FILES.iter() {
+        let fd = open(file, OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd > 0);
+        close(fd as usize);
+    }
+    let fd = open("gd\0", OpenFlags::RDONLY | OpenFlags::DIRECTORY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+
+    // a buffer that holds only one record at a time
+    let mut small = [0u8; 24];
+    let names = read_all(fd, &mut small);
+    assert_eq!(names.len(), 2 + 1 + FILES.len());
+    assert!(names.iter().any(|(name, t)| name == "sub" && *t == DT_DIR));
+    for i in 0..FILES.len() {
+        let name = &FILES[i][3..5];
+        assert_eq!(names.iter().filter(|(n, t)| n == name && *t == DT_REG).count(), 1);
+    }
+    // at the end
+    assert_eq!(getdents64(fd, &mut small), 0);
+
+    // rewind and read everything at once
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut big = [0u8; 1024];
+    assert_eq!(read_all(fd, &mut big), names);
+
+    // too small for a single record, or not a directory
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(getdents64(fd, &mut small[..8]), -1);
+    close(fd);
+    let file_fd = open(FILES[0], OpenFlags::RDONLY) as usize;
+    assert_eq!(getdents64(file_fd, &mut big), -1);
+    close(file_fd);
+    assert_eq!(getdents64(100, &mut big), -1);
+
+    for file in FILES.iter() {
+        assert_eq!(unlink(file), 0);
+    }
+    assert_eq!(rmdir("gd/sub\0"), 0);
+    assert_eq!(rmdir("gd\0"), 0);
+    println!("Test getdents OK!");
+    0
+}
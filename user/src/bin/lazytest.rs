@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mmap, munmap, pipe, read, write, PROT_READ, PROT_WRITE, MAP_PRIVATE, MAP_ANONYMOUS};
+
+/// 测试用到才分配的 mmap，输出 Test lazy mmap OK! 就算正确。
+/// 映射一块比物理内存还大的区域，只碰其中几页，没碰过的页面不占物理页帧。
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x10000000;
+// 32 MiB, much more than the 8 MiB of physical memory
+const LEN: usize = 32 * 1024 * 1024;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mmap(START, LEN, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0), START as isize);
+    // an overlapping hint is not taken although nothing is allocated yet, the kernel picks another place
+    let other = mmap(START + LEN - PAGE_SIZE, PAGE_SIZE, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert!(other > 0);
+    assert!(other as usize >= START + LEN || other as usize + PAGE_SIZE <= START);
+    assert_eq!(munmap(other as usize, PAGE_SIZE), PAGE_SIZE as isize);
+    let region = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, LEN) };
+    for page in (0..LEN / PAGE_SIZE).step_by(1024) {
+        // untouched pages read as zero
+        assert_eq!(region[page * PAGE_SIZE + 7], 0);
+        region[page * PAGE_SIZE + 7] = (page / 1024) as u8 + 1;
+    }
+    for page in (0..LEN / PAGE_SIZE).step_by(1024) {
+        assert_eq!(region[page * PAGE_SIZE + 7], (page / 1024) as u8 + 1);
+    }
+
+    // the kernel can read from and write to pages the program never touched
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let untouched = &mut region[PAGE_SIZE * 10..PAGE_SIZE * 10 + 16];
+    assert_eq!(write(pipe_fd[1], untouched), 16);
+    let untouched = &mut region[PAGE_SIZE * 20..PAGE_SIZE * 20 + 16];
+    assert_eq!(read(pipe_fd[0], untouched), 16);
+    assert!(untouched.iter().all(|&b| b == 0));
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    assert_eq!(munmap(START, LEN), LEN as isize);
+    println!("Test lazy mmap OK!");
+    0
+}
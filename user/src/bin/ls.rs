@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use user_lib::{
+    close, getdents64, open, parse_dirents, stat, OpenFlags, Stat, StatMode, DT_DIR, DT_LNK,
+};
+
+/// 列出目录里的文件，目录后面加 /，符号链接后面加 @；不带参数就列出当前目录
+fn list(path: &str) -> i32 {
+    let fd = open(path, OpenFlags::RDONLY | OpenFlags::DIRECTORY);
+    if fd < 0 {
+        // not a directory, just print its name like ls does
+        let st = Stat::new();
+        if stat(path, &st) == 0 && st.file_type() == StatMode::FILE {
+            println!("{}", path);
+            return 0;
+        }
+        println!("ls: cannot access {}", path);
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut buf = [0u8; 512];
+    loop {
+        let len = getdents64(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        for entry in parse_dirents(&buf[..len as usize]) {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            if entry.d_type == DT_DIR {
+                println!("{}/", entry.name);
+            } else if entry.d_type == DT_LNK {
+                println!("{}@", entry.name);
+            } else {
+                println!("{}", entry.name);
+            }
+        }
+    }
+    close(fd);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        return list(".\0");
+    }
+    let mut result = 0;
+    for path in argv[1..].iter() {
+        if argc > 2 {
+            println!("{}:", path);
+        }
+        if list(path) != 0 {
+            result = -1;
+        }
+    }
+    result
+}
@@ -0,0 +1,137 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, mmap, msync, munmap, open, pread, unlink, wait, write, OpenFlags,
+    MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, MS_SYNC, PROT_READ, PROT_WRITE,
+};
+
+/// 测试完整的 mmap：文件映射、MAP_SHARED/MAP_PRIVATE、内核挑地址、MAP_FIXED，输出 Test mmap OK! 就算正确。
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x10000000;
+// two full pages and a bit of the third one
+const FILE_SIZE: usize = 2 * PAGE_SIZE + 100;
+
+fn pattern(offset: usize) -> u8 {
+    (offset % 251) as u8
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("mmaptest\0", OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; FILE_SIZE];
+    for (offset, b) in buffer.iter_mut().enumerate() {
+        *b = pattern(offset);
+    }
+    assert_eq!(write(fd, &buffer), FILE_SIZE as isize);
+
+    // bad arguments
+    let rw = PROT_READ | PROT_WRITE;
+    assert_eq!(mmap(START, PAGE_SIZE, rw, MAP_ANONYMOUS, -1, 0), -1);
+    assert_eq!(mmap(START, PAGE_SIZE, rw, MAP_SHARED | MAP_PRIVATE | MAP_ANONYMOUS, -1, 0), -1);
+    assert_eq!(mmap(START, PAGE_SIZE, rw, MAP_SHARED, fd as isize, 100), -1);
+    assert_eq!(mmap(START, PAGE_SIZE, rw, MAP_SHARED, 99, 0), -1);
+    assert_eq!(mmap(START, 0, rw, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0), -1);
+    // ranges that wrap around or leave the user address space
+    let fixed = MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED;
+    assert_eq!(mmap(usize::MAX - PAGE_SIZE + 1, 2 * PAGE_SIZE, rw, fixed, -1, 0), -1);
+    assert_eq!(mmap(0x7f_ffff_f000, PAGE_SIZE, rw, fixed, -1, 0), -1);
+    assert_eq!(mmap((1 << 38) - PAGE_SIZE, 2 * PAGE_SIZE, rw, fixed, -1, 0), -1);
+
+    // a private file mapping sees the file but never writes back
+    let len = 3 * PAGE_SIZE;
+    assert_eq!(mmap(START, len, rw, MAP_PRIVATE | MAP_FIXED, fd as isize, 0), START as isize);
+    let region = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, len) };
+    for offset in (0..FILE_SIZE).step_by(97) {
+        assert_eq!(region[offset], pattern(offset));
+    }
+    // past the end of the file reads as zero
+    assert!(region[FILE_SIZE..].iter().all(|&b| b == 0));
+    region[0] = 0xaa;
+    assert_eq!(munmap(START, len), len as isize);
+    let mut byte = [0u8; 1];
+    assert_eq!(pread(fd, &mut byte, 0), 1);
+    assert_eq!(byte[0], pattern(0));
+
+    // a shared file mapping starting at the second page is written back by msync and munmap
+    assert_eq!(mmap(START, 2 * PAGE_SIZE, rw, MAP_SHARED, fd as isize, PAGE_SIZE), START as isize);
+    let region = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, 2 * PAGE_SIZE) };
+    assert_eq!(region[0], pattern(PAGE_SIZE));
+    region[1] = 0x11;
+    assert_eq!(msync(START, PAGE_SIZE, MS_SYNC), 0);
+    assert_eq!(pread(fd, &mut byte, PAGE_SIZE + 1), 1);
+    assert_eq!(byte[0], 0x11);
+    region[PAGE_SIZE + 50] = 0x22;
+    // writes past the end of the file are dropped, the file does not grow
+    region[PAGE_SIZE + 200] = 0x33;
+    assert_eq!(munmap(START, 2 * PAGE_SIZE), (2 * PAGE_SIZE) as isize);
+    assert_eq!(pread(fd, &mut byte, 2 * PAGE_SIZE + 50), 1);
+    assert_eq!(byte[0], 0x22);
+    assert_eq!(pread(fd, &mut byte, 2 * PAGE_SIZE + 200), 0);
+
+    // a read-only file cannot be mapped shared and writable
+    let ro = open("mmaptest\0", OpenFlags::RDONLY);
+    assert!(ro > 0);
+    assert_eq!(mmap(START, PAGE_SIZE, rw, MAP_SHARED, ro, 0), -1);
+    assert_eq!(mmap(START, PAGE_SIZE, PROT_READ, MAP_SHARED, ro, 0), START as isize);
+    // the mapping outlives the file descriptor
+    close(ro as usize);
+    assert_eq!(unsafe { *((START + 1) as *const u8) }, 0x11);
+    assert_eq!(munmap(START, PAGE_SIZE), PAGE_SIZE as isize);
+    close(fd);
+    assert_eq!(unlink("mmaptest\0"), 0);
+
+    // shared anonymous memory is still shared after fork, private memory is not
+    assert_eq!(mmap(START, PAGE_SIZE, rw, MAP_SHARED | MAP_ANONYMOUS, -1, 0), START as isize);
+    let private = mmap(0, PAGE_SIZE, rw, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    // the kernel picks a free page-aligned address
+    assert!(private > 0 && private as usize != START && private as usize % PAGE_SIZE == 0);
+    let shared = unsafe { &mut *(START as *mut u64) };
+    let private = unsafe { &mut *(private as usize as *mut u64) };
+    *shared = 1;
+    *private = 1;
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(*shared, 1);
+        *shared = 42;
+        *private = 42;
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(*shared, 42);
+    assert_eq!(*private, 1);
+    assert_eq!(munmap(START, PAGE_SIZE), PAGE_SIZE as isize);
+
+    // without MAP_FIXED the address is only a hint
+    let anon = MAP_PRIVATE | MAP_ANONYMOUS;
+    assert_eq!(mmap(START, 3 * PAGE_SIZE, rw, anon, -1, 0), START as isize);
+    let moved = mmap(START + PAGE_SIZE, PAGE_SIZE, rw, anon, -1, 0);
+    assert!(moved > 0 && moved as usize != START + PAGE_SIZE);
+    assert_eq!(munmap(moved as usize, PAGE_SIZE), PAGE_SIZE as isize);
+    let moved = mmap(0x7f_ffff_f000, PAGE_SIZE, rw, anon, -1, 0);
+    assert!(moved > 0 && (moved as usize) < 1 << 38);
+    assert_eq!(munmap(moved as usize, PAGE_SIZE), PAGE_SIZE as isize);
+    // MAP_FIXED replaces whatever was mapped there, the pages around it keep their contents
+    let region = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, 3 * PAGE_SIZE) };
+    region[0] = 1;
+    region[PAGE_SIZE] = 2;
+    region[2 * PAGE_SIZE] = 3;
+    assert_eq!(mmap(START + PAGE_SIZE, PAGE_SIZE, PROT_READ, anon | MAP_FIXED, -1, 0), (START + PAGE_SIZE) as isize);
+    assert_eq!(region[PAGE_SIZE], 0);
+    assert_eq!(region[0], 1);
+    assert_eq!(region[2 * PAGE_SIZE], 3);
+    assert_eq!(munmap(START, 3 * PAGE_SIZE), (3 * PAGE_SIZE) as isize);
+    // msync over pages that are not mapped
+    assert_eq!(msync(START, PAGE_SIZE, MS_SYNC), -1);
+    assert_eq!(msync(usize::MAX - PAGE_SIZE + 1, 2 * PAGE_SIZE, MS_SYNC), -1);
+    println!("Test mmap OK!");
+    0
+}
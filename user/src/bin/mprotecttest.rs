@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+#![feature(llvm_asm)]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mmap, mprotect, munmap, wait, MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC, PROT_READ,
+    PROT_WRITE,
+};
+
+/// 测试 mprotect 和只去掉一部分的 munmap，输出 Test mprotect OK! 就算正确。
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x10000000;
+const PAGES: usize = 4;
+
+fn page(i: usize) -> *mut u8 {
+    (START + i * PAGE_SIZE) as *mut u8
+}
+
+/// 在子进程里访问 addr，子进程应该因为缺页被杀掉
+fn expect_fault(addr: *mut u8, write: bool) {
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            if write {
+                addr.write_volatile(0xff);
+            } else {
+                addr.read_volatile();
+            }
+        }
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, -2);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let rw = PROT_READ | PROT_WRITE;
+    let len = PAGES * PAGE_SIZE;
+    assert_eq!(mmap(START, len, rw, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0), START as isize);
+    for i in 0..PAGES {
+        unsafe { *page(i) = i as u8 + 1 };
+    }
+
+    // make the two middle pages read-only
+    assert_eq!(mprotect(START + PAGE_SIZE, 2 * PAGE_SIZE, PROT_READ), 0);
+    unsafe {
+        assert_eq!(*page(1), 2);
+        assert_eq!(*page(2), 3);
+        *page(0) = 0x10;
+        *page(3) = 0x40;
+    }
+    expect_fault(page(1), true);
+    expect_fault(page(2), true);
+    // ranges with holes or outside any mapping are rejected
+    assert_eq!(mprotect(START - PAGE_SIZE, 2 * PAGE_SIZE, rw), -1);
+    assert_eq!(mprotect(START + len, PAGE_SIZE, rw), -1);
+    assert_eq!(mprotect(START, PAGE_SIZE, 0), -1);
+
+    // unmap the first page only, the rest keeps its contents and permissions
+    assert_eq!(munmap(START, PAGE_SIZE), PAGE_SIZE as isize);
+    expect_fault(page(0), false);
+    unsafe {
+        assert_eq!(*page(1), 2);
+        assert_eq!(*page(3), 0x40);
+    }
+    // the first page is free again, a new mapping can take its place
+    assert_eq!(mmap(START, PAGE_SIZE, rw, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0), START as isize);
+    unsafe { assert_eq!(*page(0), 0) };
+    assert_eq!(munmap(START, PAGE_SIZE), PAGE_SIZE as isize);
+    assert_eq!(munmap(START, 2 * PAGE_SIZE), -1);
+
+    // make the pages writable again, they merge back into one area
+    assert_eq!(mprotect(START + PAGE_SIZE, 2 * PAGE_SIZE, rw), 0);
+    unsafe {
+        *page(1) = 0x20;
+        *page(2) = 0x30;
+    }
+    // punch a hole in the middle, then unmap the pieces around it
+    assert_eq!(munmap(START + 2 * PAGE_SIZE, PAGE_SIZE), PAGE_SIZE as isize);
+    expect_fault(page(2), false);
+    unsafe {
+        assert_eq!(*page(1), 0x20);
+        assert_eq!(*page(3), 0x40);
+    }
+    assert_eq!(munmap(START + PAGE_SIZE, 3 * PAGE_SIZE), -1);
+    assert_eq!(munmap(START + PAGE_SIZE, PAGE_SIZE), PAGE_SIZE as isize);
+    assert_eq!(munmap(START + 3 * PAGE_SIZE, PAGE_SIZE), PAGE_SIZE as isize);
+
+    // JIT: write code into a writable page, then make it executable
+    let code = mmap(0, PAGE_SIZE, rw, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert!(code > 0);
+    let code = code as usize;
+    unsafe {
+        // li a0, 42; ret
+        (code as *mut u32).write_volatile(0x02a00513);
+        ((code + 4) as *mut u32).write_volatile(0x00008067);
+    }
+    assert_eq!(mprotect(code, PAGE_SIZE, PROT_READ | PROT_EXEC), 0);
+    expect_fault(code as *mut u8, true);
+    unsafe {
+        llvm_asm!("fence.i" :::: "volatile");
+        let f: extern "C" fn() -> usize = core::mem::transmute(code);
+        assert_eq!(f(), 42);
+    }
+    assert_eq!(munmap(code, PAGE_SIZE), PAGE_SIZE as isize);
+    println!("Test mprotect OK!");
+    0
+}
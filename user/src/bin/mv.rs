@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{rename, stat, Stat, StatMode};
+
+/// mv <源> <目标>：目标是已经存在的目录的话，就挪到这个目录下面，名字不变
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 3 {
+        println!("usage: mv <source> <target>");
+        return -1;
+    }
+    let source = argv[1].trim_end_matches('/');
+    let mut target = String::from(argv[2]);
+    let st = Stat::new();
+    if stat(argv[2], &st) == 0 && st.file_type() == StatMode::DIR {
+        let name = match source.rfind('/') {
+            Some(pos) => &source[pos + 1..],
+            None => source,
+        };
+        if !target.ends_with('/') {
+            target.push('/');
+        }
+        target.push_str(name);
+    }
+    let mut source = String::from(source);
+    source.push('\0');
+    target.push('\0');
+    if rename(source.as_str(), target.as_str()) != 0 {
+        println!("mv: cannot move {} to {}", argv[1], argv[2]);
+        return -1;
+    }
+    0
+}
@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exec, fork, lseek, mkdir, open, read, rmdir, unlink, waitpid, write, OpenFlags,
+    SEEK_CUR, SEEK_SET,
+};
+
+/// 测试 APPEND、CREATE/TRUNC、EXCL、DIRECTORY、CLOEXEC 和不合法的标志组合，输出　Test open flags OK! 就算正确。
+/// 它会 exec 自己一次，用 child 参数检查 CLOEXEC 的 fd 有没有被关掉。
+
+const CHILD_ARGS: [&str; 4] = ["openflagtest\0", "child\0", "3\0", "4\0"];
+
+fn child(keep_fd: usize, cloexec_fd: usize) -> i32 {
+    if lseek(keep_fd, 0, SEEK_CUR) < 0 {
+        return 1;
+    }
+    if lseek(cloexec_fd, 0, SEEK_CUR) != -1 || close(cloexec_fd) != -1 {
+        return 2;
+    }
+    close(keep_fd);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 4 && argv[1] == "child" {
+        return child(argv[2].parse().unwrap(), argv[3].parse().unwrap());
+    }
+
+    // APPEND: every write goes to the end, even after a seek
+    let fd = open("appendf\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"abc"), 3);
+    close(fd as usize);
+    let fd = open("appendf\0", OpenFlags::RDWR | OpenFlags::APPEND);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(write(fd, b"def"), 3);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 6);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut buffer = [0u8; 16];
+    assert_eq!(read(fd, &mut buffer), 6);
+    assert_eq!(&buffer[..6], b"abcdef");
+    close(fd);
+
+    // CREATE keeps what an existing file has, only TRUNC clears it
+    let fd = open("appendf\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    let fd = open("appendf\0", OpenFlags::RDONLY);
+    assert_eq!(read(fd as usize, &mut buffer), 6);
+    close(fd as usize);
+    let fd = open("appendf\0", OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    close(fd as usize);
+    let fd = open("appendf\0", OpenFlags::RDONLY);
+    assert_eq!(read(fd as usize, &mut buffer), 0);
+    close(fd as usize);
+
+    // EXCL: only create, never open an existing file
+    assert_eq!(open("appendf\0", OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::WRONLY), -1);
+    let fd = open("exclf\0", OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(unlink("exclf\0"), 0);
+
+    // DIRECTORY: only directories
+    assert_eq!(mkdir("flagdir\0"), 0);
+    let fd = open("flagdir\0", OpenFlags::DIRECTORY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(open("appendf\0", OpenFlags::DIRECTORY), -1);
+    assert_eq!(rmdir("flagdir\0"), 0);
+
+    // invalid combinations
+    assert_eq!(open("appendf\0", OpenFlags::WRONLY | OpenFlags::RDWR), -1);
+    assert_eq!(open("appendf\0", OpenFlags::RDONLY | OpenFlags::TRUNC), -1);
+    assert_eq!(open("appendf\0", OpenFlags::EXCL | OpenFlags::WRONLY), -1);
+    assert_eq!(open("newdir\0", OpenFlags::DIRECTORY | OpenFlags::CREATE), -1);
+    assert_eq!(open("appendf\0", unsafe { OpenFlags::from_bits_unchecked(1 << 30) }), -1);
+
+    // CLOEXEC: closed by exec, the other fd survives
+    let keep_fd = open("appendf\0", OpenFlags::RDONLY);
+    let cloexec_fd = open("appendf\0", OpenFlags::RDONLY | OpenFlags::CLOEXEC);
+    assert_eq!((keep_fd, cloexec_fd), (3, 4));
+    let pid = fork();
+    if pid == 0 {
+        let args = [
+            CHILD_ARGS[0].as_ptr(),
+            CHILD_ARGS[1].as_ptr(),
+            CHILD_ARGS[2].as_ptr(),
+            CHILD_ARGS[3].as_ptr(),
+            0 as *const u8,
+        ];
+        exec(CHILD_ARGS[0], &args);
+        panic!("unreachable!");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // the parent itself still has both
+    assert_eq!(lseek(cloexec_fd as usize, 0, SEEK_CUR), 0);
+    close(keep_fd as usize);
+    close(cloexec_fd as usize);
+    assert_eq!(unlink("appendf\0"), 0);
+    println!("Test open flags OK!");
+    0
+}
@@ -0,0 +1,132 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, chmod, chown, close, exec, exit, fork, getgid, getuid, mkdir, open, rename, rmdir,
+    setgid, setuid, stat, unlink, waitpid, write, OpenFlags, Stat, StatMode,
+};
+
+/// 测试权限位和属主，子进程换成普通用户以后不能改 initproc 和 shell，
+/// 输出　Test permission OK! 就算正确。
+
+const USER: u32 = 1000;
+const GROUP: u32 = 100;
+
+fn create_file(path: &str) -> isize {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    if fd < 0 {
+        return -1;
+    }
+    assert_eq!(write(fd as usize, b"data"), 4);
+    close(fd as usize);
+    0
+}
+
+// 在子进程里跑，出错就panic，退出码不是0
+fn as_user() {
+    assert_eq!(setgid(GROUP), 0);
+    assert_eq!(setuid(USER), 0);
+    assert_eq!((getuid(), getgid()), (USER as isize, GROUP as isize));
+    // no way back to root
+    assert_eq!(setuid(0), -1);
+    assert_eq!(setgid(0), -1);
+
+    // the system binaries can be run and read but not changed
+    for app in ["initproc\0", "user_shell\0"].iter() {
+        assert_eq!(open(app, OpenFlags::WRONLY), -1);
+        assert_eq!(open(app, OpenFlags::RDWR | OpenFlags::TRUNC), -1);
+        assert_eq!(open(app, OpenFlags::CREATE), -1);
+        assert_eq!(unlink(app), -1);
+        assert_eq!(rename(app, "pm_stolen\0"), -1);
+        assert_eq!(rename("pm_user_file\0", app), -1);
+        assert_eq!(chmod(app, 0o777), -1);
+        assert_eq!(chown(app, USER, GROUP), -1);
+        let fd = open(app, OpenFlags::RDONLY);
+        assert!(fd > 0);
+        close(fd as usize);
+    }
+
+    // the root is sticky: new files are ours and can be removed again
+    assert_eq!(create_file("pm_mine\0"), 0);
+    let st = Stat::new();
+    assert_eq!(stat("pm_mine\0", &st), 0);
+    assert_eq!((st.uid, st.gid, st.perm()), (USER, GROUP, 0o644));
+    assert_eq!(rename("pm_mine\0", "pm_mine2\0"), 0);
+    assert_eq!(unlink("pm_mine2\0"), 0);
+
+    // a file handed over by root, then taken away again with chmod
+    assert_eq!(create_file("pm_user_file\0"), 0);
+    assert_eq!(chmod("pm_user_file\0", 0o400), 0);
+    assert_eq!(open("pm_user_file\0", OpenFlags::WRONLY), -1);
+    assert!(open("pm_user_file\0", OpenFlags::RDONLY) > 0);
+    assert_eq!(chown("pm_user_file\0", 0, u32::MAX), -1);
+    assert_eq!(chmod("pm_user_file\0", 0o600), 0);
+
+    // root's directory without write permission for others
+    assert_eq!(create_file("pm_dir/file\0"), -1);
+    assert_eq!(mkdir("pm_dir/sub\0"), -1);
+    assert_eq!(unlink("pm_dir/root_file\0"), -1);
+    assert_eq!(open("pm_dir/root_file\0", OpenFlags::RDONLY), -1);
+
+    // no x on a directory, no chdir into it
+    assert_eq!(chdir("pm_dir/closed\0"), -1);
+    assert_eq!(chdir("pm_dir\0"), 0);
+    assert_eq!(chdir("..\0"), 0);
+
+    // no x, no exec
+    assert_eq!(exec("pm_user_file\0", &[core::ptr::null::<u8>()]), -1);
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(getuid(), 0);
+    let st = Stat::new();
+    assert_eq!(stat("/\0", &st), 0);
+    assert_eq!(st.perm(), 0o1777);
+    assert_eq!(stat("initproc\0", &st), 0);
+    assert_eq!((st.file_type(), st.perm(), st.uid), (StatMode::FILE, 0o755, 0));
+
+    assert_eq!(mkdir("pm_dir\0"), 0);
+    assert_eq!(stat("pm_dir\0", &st), 0);
+    assert_eq!((st.file_type(), st.perm()), (StatMode::DIR, 0o755));
+    assert_eq!(create_file("pm_dir/root_file\0"), 0);
+    assert_eq!(chmod("pm_dir/root_file\0", 0o600), 0);
+    assert_eq!(stat("pm_dir/root_file\0", &st), 0);
+    assert_eq!(st.perm(), 0o600);
+    assert_eq!(mkdir("pm_dir/closed\0"), 0);
+    assert_eq!(chmod("pm_dir/closed\0", 0o700), 0);
+
+    // setuid bits go away when the owner changes
+    assert_eq!(create_file("pm_user_file\0"), 0);
+    assert_eq!(chmod("pm_user_file\0", 0o4755), 0);
+    assert_eq!(chown("pm_user_file\0", USER, GROUP), 0);
+    assert_eq!(stat("pm_user_file\0", &st), 0);
+    assert_eq!((st.uid, st.gid, st.perm()), (USER, GROUP, 0o755));
+    assert_eq!(chmod("pm_user_file\0", 0o644), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        as_user();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // root is not bound by the permission bits
+    assert_eq!(stat("pm_user_file\0", &st), 0);
+    assert_eq!(st.perm(), 0o600);
+    assert_eq!(chmod("pm_user_file\0", 0), 0);
+    let fd = open("pm_user_file\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(unlink("pm_user_file\0"), 0);
+    assert_eq!(unlink("pm_dir/root_file\0"), 0);
+    assert_eq!(rmdir("pm_dir/closed\0"), 0);
+    assert_eq!(rmdir("pm_dir\0"), 0);
+    println!("Test permission OK!");
+    0
+}
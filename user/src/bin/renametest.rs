@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    chdir, close, fstat, mkdir, open, read, rename, renameat2, rmdir, stat, unlink, write, OpenFlags,
+    Stat, AT_FDCWD, RENAME_NOREPLACE,
+};
+
+/// 测试 renameat2，输出　Test rename OK! 就算正确。
+
+fn read_file(path: &str, buffer: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return -1;
+    }
+    let len = read(fd as usize, buffer);
+    close(fd as usize);
+    len
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buffer = [0u8; 32];
+    write_file("rn_a\0", b"aaa");
+    let st = Stat::new();
+    assert_eq!(stat("rn_a\0", &st), 0);
+
+    // same directory keeps the inode
+    assert_eq!(rename("rn_a\0", "rn_b\0"), 0);
+    assert_eq!(read_file("rn_a\0", &mut buffer), -1);
+    assert_eq!(read_file("rn_b\0", &mut buffer), 3);
+    let st2 = Stat::new();
+    assert_eq!(stat("rn_b\0", &st2), 0);
+    assert_eq!(st2.ino, st.ino);
+
+    // into another directory, replacing an existing file
+    assert_eq!(mkdir("rn_dir\0"), 0);
+    write_file("rn_dir/old\0", b"old file");
+    assert_eq!(renameat2(AT_FDCWD, "rn_b\0", AT_FDCWD, "rn_dir/old\0", RENAME_NOREPLACE), -1);
+    // an open fd on the replaced file still works until it is closed
+    let old_fd = open("rn_dir/old\0", OpenFlags::RDONLY) as usize;
+    assert_eq!(rename("rn_b\0", "rn_dir/old\0"), 0);
+    assert_eq!(read_file("rn_dir/old\0", &mut buffer), 3);
+    assert_eq!(&buffer[..3], b"aaa");
+    assert_eq!(read(old_fd, &mut buffer), 8);
+    assert_eq!(fstat(old_fd, &st2), 0);
+    assert_eq!(st2.nlink, 0);
+    close(old_fd);
+
+    // directories move with their contents, but never below themselves
+    assert_eq!(mkdir("rn_dir/sub\0"), 0);
+    assert_eq!(rename("rn_dir\0", "rn_dir/sub/x\0"), -1);
+    assert_eq!(rename("rn_dir/sub\0", "rn_sub\0"), 0);
+    assert_eq!(rename("rn_dir\0", "rn_sub/moved\0"), 0);
+    assert_eq!(read_file("rn_sub/moved/old\0", &mut buffer), 3);
+    assert_eq!(stat("rn_sub/moved/..\0", &st2), 0);
+    assert_eq!(stat("rn_sub\0", &st), 0);
+    assert_eq!(st2.ino, st.ino);
+    // a file cannot replace a directory, and a non-empty directory cannot be replaced
+    assert_eq!(rename("rn_sub/moved/old\0", "rn_sub/moved\0"), -1);
+    assert_eq!(mkdir("rn_empty\0"), 0);
+    assert_eq!(rename("rn_empty\0", "rn_sub\0"), -1);
+    assert_eq!(rename("nothere\0", "rn_x\0"), -1);
+    assert_eq!(renameat2(AT_FDCWD, "rn_empty\0", AT_FDCWD, "rn_x\0", 2), -1);
+
+    // the working directory is the directory itself, renaming an ancestor does not lose it
+    assert_eq!(mkdir("rn_cwd\0"), 0);
+    assert_eq!(mkdir("rn_cwd/inner\0"), 0);
+    assert_eq!(chdir("rn_cwd/inner\0"), 0);
+    write_file("here\0", b"here");
+    assert_eq!(rename("/rn_cwd\0", "/rn_cwd2\0"), 0);
+    assert_eq!(read_file("here\0", &mut buffer), 4);
+    assert_eq!(read_file("../inner/here\0", &mut buffer), 4);
+    assert_eq!(read_file("/rn_cwd2/inner/here\0", &mut buffer), 4);
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(unlink("rn_cwd2/inner/here\0"), 0);
+    assert_eq!(rmdir("rn_cwd2/inner\0"), 0);
+    assert_eq!(rmdir("rn_cwd2\0"), 0);
+
+    assert_eq!(unlink("rn_sub/moved/old\0"), 0);
+    assert_eq!(rmdir("rn_sub/moved\0"), 0);
+    assert_eq!(rmdir("rn_sub\0"), 0);
+    assert_eq!(rmdir("rn_empty\0"), 0);
+    println!("Test rename OK!");
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, lseek, open, pipe, pread, pwrite, read, unlink, write, OpenFlags,
+    SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+/// 测试 lseek、pread 和 pwrite，输出　Test seek OK! 就算正确。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("seekf\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello world"), 11);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 11);
+    assert_eq!(lseek(fd, 6, SEEK_SET), 6);
+    let mut buffer = [0u8; 32];
+    assert_eq!(read(fd, &mut buffer[..5]), 5);
+    assert_eq!(&buffer[..5], b"world");
+    assert_eq!(lseek(fd, -5, SEEK_END), 6);
+    assert_eq!(lseek(fd, -7, SEEK_CUR), -1);
+    // the offset is unchanged after a failed seek
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 6);
+    assert_eq!(lseek(fd, 0, 3), -1);
+
+    // writing past the end leaves a hole of zeros
+    assert_eq!(lseek(fd, 20, SEEK_SET), 20);
+    assert_eq!(write(fd, b"!"), 1);
+    assert_eq!(lseek(fd, 0, SEEK_END), 21);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(read(fd, &mut buffer), 21);
+    assert!(buffer[11..20].iter().all(|&b| b == 0));
+    assert_eq!(buffer[20], b'!');
+
+    // pread / pwrite do not move the offset
+    assert_eq!(lseek(fd, 3, SEEK_SET), 3);
+    assert_eq!(pwrite(fd, b"W", 6), 1);
+    assert_eq!(pread(fd, &mut buffer[..5], 6), 5);
+    assert_eq!(&buffer[..5], b"World");
+    assert_eq!(pread(fd, &mut buffer, 100), 0);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 3);
+    close(fd);
+
+    // pipes and stdio cannot seek
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(lseek(pipe_fd[0], 0, SEEK_SET), -1);
+    assert_eq!(pread(pipe_fd[0], &mut buffer, 0), -1);
+    assert_eq!(pwrite(pipe_fd[1], b"x", 0), -1);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(lseek(0, 0, SEEK_CUR), -1);
+    assert_eq!(lseek(100, 0, SEEK_SET), -1);
+    assert_eq!(unlink("seekf\0"), 0);
+    println!("Test seek OK!");
+    0
+}
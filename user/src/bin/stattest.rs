@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, mkdir, open, pipe, read, rmdir, sleep, stat, unlink, write, OpenFlags, Stat,
+    StatMode,
+};
+
+/// 测试 fstat 的大小、时间戳和类型，还有 fstatat，输出　Test stat OK! 就算正确。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let data = [0x5au8; 1000];
+    let fd = open("statf\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, &data), 1000);
+    let st = Stat::new();
+    assert_eq!(fstat(fd, &st), 0);
+    assert_eq!(st.file_type(), StatMode::FILE);
+    assert_eq!(st.size, 1000);
+    assert_eq!(st.nlink, 1);
+    assert!(st.mtime >= st.atime && st.ctime == st.mtime);
+    close(fd);
+
+    // reading only moves atime
+    sleep(20);
+    let fd = open("statf\0", OpenFlags::RDONLY) as usize;
+    let mut buffer = [0u8; 16];
+    assert_eq!(read(fd, &mut buffer), 16);
+    let st2 = Stat::new();
+    assert_eq!(fstat(fd, &st2), 0);
+    assert!(st2.atime > st.atime);
+    assert_eq!((st2.mtime, st2.ctime), (st.mtime, st.ctime));
+    close(fd);
+
+    // by path, no fd needed
+    let st3 = Stat::new();
+    assert_eq!(stat("statf\0", &st3), 0);
+    assert_eq!((st3.ino, st3.size), (st.ino, 1000));
+    assert_eq!(mkdir("statdir\0"), 0);
+    assert_eq!(stat("statdir\0", &st3), 0);
+    assert_eq!(st3.file_type(), StatMode::DIR);
+    assert_eq!(stat("/\0", &st3), 0);
+    assert_eq!((st3.file_type(), st3.ino), (StatMode::DIR, 0));
+    assert_eq!(stat("nothere\0", &st3), -1);
+    assert_eq!(rmdir("statdir\0"), 0);
+
+    // pipes and stdio
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fstat(pipe_fd[0], &st3), 0);
+    assert_eq!((st3.file_type(), st3.size), (StatMode::FIFO, 0));
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(fstat(0, &st3), 0);
+    assert_eq!(st3.file_type(), StatMode::CHR);
+    assert_eq!(fstat(100, &st3), -1);
+    assert_eq!(unlink("statf\0"), 0);
+    println!("Test stat OK!");
+    0
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, mmap, munmap, open, rename, unlink, OpenFlags, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ,
+    PROT_WRITE,
+};
+
+/// 测试换页，输出 Test swap OK! 就算正确。
+/// 往一块和物理内存一样大的区域里每一页都写上不同的内容，
+/// 物理页帧不够的时候一部分页面会被换到交换文件里，再读回来内容要对得上。
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x10000000;
+// as large as all physical memory, the kernel and other programs included
+const LEN: usize = 8 * 1024 * 1024;
+
+fn pattern(page: usize, offset: usize) -> u8 {
+    (page * 7 + offset * 13) as u8
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mmap(START, LEN, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0), START as isize);
+    let region = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, LEN) };
+    // a path kept in the first page, which is swapped out by the time it is used
+    region[100..108].copy_from_slice(b"sw_path\0");
+    for page in 0..LEN / PAGE_SIZE {
+        for offset in (0..PAGE_SIZE).step_by(512) {
+            region[page * PAGE_SIZE + offset] = pattern(page, offset);
+        }
+    }
+    // read everything back twice, so pages swapped in are swapped out again
+    for _ in 0..2 {
+        for page in 0..LEN / PAGE_SIZE {
+            for offset in (0..PAGE_SIZE).step_by(512) {
+                assert_eq!(region[page * PAGE_SIZE + offset], pattern(page, offset));
+            }
+        }
+    }
+    // rewrite half of the pages after they have been swapped in
+    for page in (0..LEN / PAGE_SIZE).step_by(2) {
+        region[page * PAGE_SIZE] = !pattern(page, 0);
+    }
+    for page in 0..LEN / PAGE_SIZE {
+        let expected = if page % 2 == 0 { !pattern(page, 0) } else { pattern(page, 0) };
+        assert_eq!(region[page * PAGE_SIZE], expected);
+    }
+
+    // the kernel swaps the page with the path back in instead of panicking
+    let path = unsafe { core::str::from_utf8_unchecked(&region[100..108]) };
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    // the swap file is in use by the kernel and cannot be touched
+    assert_eq!(open("/.swap\0", OpenFlags::RDONLY), -1);
+    assert_eq!(unlink("/.swap\0"), -1);
+    assert_eq!(rename("/.swap\0", "/sw_moved\0"), -1);
+    assert_eq!(rename(path, "/.swap\0"), -1);
+    assert_eq!(unlink(path), 0);
+    assert_eq!(munmap(START, LEN), LEN as isize);
+    println!("Test swap OK!");
+    0
+}
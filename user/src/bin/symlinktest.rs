@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, lstat, mkdir, open, read, readlink, rmdir, stat, symlink, unlink, write, OpenFlags,
+    Stat, StatMode,
+};
+
+/// 测试符号链接，输出　Test symlink OK! 就算正确。
+
+fn read_file(path: &str, buffer: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return -1;
+    }
+    let len = read(fd as usize, buffer);
+    close(fd as usize);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buffer = [0u8; 32];
+    assert_eq!(mkdir("sl_dir\0"), 0);
+    let fd = open("sl_dir/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"hello"), 5);
+    close(fd as usize);
+
+    // relative targets are resolved from the directory holding the link
+    assert_eq!(symlink("file\0", "sl_dir/rel\0"), 0);
+    assert_eq!(symlink("sl_dir\0", "sl_d\0"), 0);
+    assert_eq!(read_file("sl_dir/rel\0", &mut buffer), 5);
+    assert_eq!(read_file("sl_d/rel\0", &mut buffer), 5);
+    assert_eq!(&buffer[..5], b"hello");
+    assert_eq!(symlink("/sl_dir/file\0", "sl_abs\0"), 0);
+    assert_eq!(read_file("sl_abs\0", &mut buffer), 5);
+    assert_eq!(symlink("sl_dir\0", "sl_d\0"), -1);
+
+    // readlink gives the target without a trailing \0
+    assert_eq!(readlink("sl_abs\0", &mut buffer), 12);
+    assert_eq!(&buffer[..12], b"/sl_dir/file");
+    assert_eq!(readlink("sl_abs\0", &mut buffer[..4]), 4);
+    assert_eq!(readlink("sl_dir/file\0", &mut buffer), -1);
+
+    // stat follows the link, lstat does not
+    let st = Stat::new();
+    let lst = Stat::new();
+    assert_eq!(stat("sl_d\0", &st), 0);
+    assert_eq!(st.file_type(), StatMode::DIR);
+    assert_eq!(lstat("sl_d\0", &lst), 0);
+    assert_eq!((lst.file_type(), lst.size), (StatMode::LNK, 6));
+    assert!(lst.ino != st.ino);
+
+    // dangling links and loops
+    assert_eq!(symlink("nothere\0", "sl_dangling\0"), 0);
+    assert_eq!(read_file("sl_dangling\0", &mut buffer), -1);
+    assert_eq!(lstat("sl_dangling\0", &lst), 0);
+    assert_eq!(symlink("sl_loop\0", "sl_loop\0"), 0);
+    assert_eq!(read_file("sl_loop\0", &mut buffer), -1);
+
+    // unlink removes the link, not what it points to
+    assert_eq!(unlink("sl_d\0"), 0);
+    assert_eq!(read_file("sl_dir/file\0", &mut buffer), 5);
+    for path in ["sl_dir/rel\0", "sl_abs\0", "sl_dangling\0", "sl_loop\0", "sl_dir/file\0"].iter() {
+        assert_eq!(unlink(path), 0);
+    }
+    assert_eq!(rmdir("sl_dir\0"), 0);
+    println!("Test symlink OK!");
+    0
+}
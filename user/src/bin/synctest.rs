@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fsync, open, read, sync, unlink, write, OpenFlags};
+
+/// 测试 sync/fsync，并且写一个比块缓存大得多的文件，输出　Test sync OK! 就算正确。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("synctest\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let buffer = [0x5au8; 512];
+    // 256 blocks, several times the default cache capacity
+    for _ in 0..256 {
+        assert_eq!(write(fd, &buffer), 512);
+    }
+    assert_eq!(fsync(fd), 0);
+    close(fd);
+    assert_eq!(fsync(fd), -1);
+    // stdin is not a disk file
+    assert_eq!(fsync(0), -1);
+    assert_eq!(sync(), 0);
+
+    let fd = open("synctest\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut total = 0usize;
+    let mut read_buffer = [0u8; 512];
+    loop {
+        let len = read(fd, &mut read_buffer);
+        if len <= 0 {
+            break;
+        }
+        assert!(read_buffer[..len as usize].iter().all(|&b| b == 0x5a));
+        total += len as usize;
+    }
+    close(fd);
+    assert_eq!(total, 256 * 512);
+    assert_eq!(unlink("synctest\0"), 0);
+    println!("Test sync OK!");
+    0
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, ftruncate, open, read, unlink, write, OpenFlags};
+
+/// 测试 ftruncate 和 O_TRUNC，输出　Test truncate OK! 就算正确。
+
+fn read_file(path: &str, buffer: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut total = 0usize;
+    loop {
+        let len = read(fd, &mut buffer[total..]);
+        if len <= 0 {
+            break;
+        }
+        total += len as usize;
+    }
+    close(fd);
+    total
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let data = [0x33u8; 2000];
+    let fd = open("trunc\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, &data), 2000);
+    // shrink into the middle of a block, then grow again
+    assert_eq!(ftruncate(fd, 700), 0);
+    assert_eq!(ftruncate(fd, 1500), 0);
+    close(fd);
+    let mut buffer = [0xffu8; 4096];
+    assert_eq!(read_file("trunc\0", &mut buffer), 1500);
+    assert!(buffer[..700].iter().all(|&b| b == 0x33));
+    assert!(buffer[700..1500].iter().all(|&b| b == 0));
+
+    // a read-only fd cannot be truncated, and O_TRUNC needs write access
+    assert_eq!(open("trunc\0", OpenFlags::RDONLY | OpenFlags::TRUNC), -1);
+    let fd = open("trunc\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(ftruncate(fd as usize, 0), -1);
+    close(fd as usize);
+    assert_eq!(read_file("trunc\0", &mut buffer), 1500);
+
+    let fd = open("trunc\0", OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(read_file("trunc\0", &mut buffer), 0);
+    assert_eq!(ftruncate(100, 0), -1);
+    assert_eq!(unlink("trunc\0"), 0);
+    println!("Test truncate OK!");
+    0
+}
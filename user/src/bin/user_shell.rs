@@ -21,6 +21,7 @@ use user_lib::{
     OpenFlags,
     close,
     dup,
+    chdir,
 };
 use user_lib::console::getchar;
 
@@ -71,6 +72,17 @@ pub fn main() -> i32 {
                         args_copy.drain(idx..=idx + 1);
                     }
 
+                    // cd 需要改变 shell 自己的工作目录，不能 fork 出去执行
+                    if args_copy[0].as_str() == "cd\0" {
+                        let path = if args_copy.len() > 1 { args_copy[1].as_str() } else { "/\0" };
+                        if chdir(path) != 0 {
+                            println!("cd: no such directory: {}", path.trim_end_matches('\0'));
+                        }
+                        line.clear();
+                        print!(">> ");
+                        continue;
+                    }
+
                     let mut args_addr: Vec<*const u8> = args_copy
                         .iter()
                         .map(|arg| arg.as_ptr())
@@ -94,7 +106,7 @@ pub fn main() -> i32 {
                         if !output.is_empty() {
                             let output_fd = open(
                                 output.as_str(),
-                                OpenFlags::CREATE | OpenFlags::WRONLY
+                                OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC
                             );
                             if output_fd == -1 {
                                 println!("Error when opening file {}", output);
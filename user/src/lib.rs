@@ -17,6 +17,7 @@ use syscall::*;
 use buddy_system_allocator::LockedHeap;
 pub use console::{STDIN, STDOUT};
 use alloc::vec::Vec;
+use alloc::string::String;
 
 #[repr(C)]
 #[derive(Debug)]
@@ -42,8 +43,17 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
+    /// user ID and group ID of owner
+    pub uid: u32,
+    pub gid: u32,
+    /// total size in bytes
+    pub size: u64,
+    /// last access / modification / status change, in ms since boot
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
     /// unused pad
-    pad: [u64; 7],
+    pad: [u64; 3],
 }
 
 impl Stat {
@@ -53,22 +63,66 @@ impl Stat {
             ino: 0,
             mode: StatMode::NULL,
             nlink: 0,
-            pad: [0; 7],
+            uid: 0,
+            gid: 0,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            pad: [0; 3],
         }
     }
+    /// mode 里去掉权限位，剩下 FILE、DIR 这些
+    pub fn file_type(&self) -> StatMode {
+        self.mode & StatMode::TYPE
+    }
+    /// mode 里的权限位，比如 0o755
+    pub fn perm(&self) -> u32 {
+        (self.mode & StatMode::PERM).bits()
+    }
 }
 
 bitflags! {
     pub struct StatMode: u32 {
         const NULL  = 0;
+        /// named pipe
+        const FIFO  = 0o010000;
+        /// character device
+        const CHR   = 0o020000;
         /// directory
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// symbolic link
+        const LNK   = 0o120000;
+        /// 上面几种类型占的位
+        const TYPE  = 0o170000;
+        /// 权限位：setuid、setgid、sticky 和 rwxrwxrwx
+        const PERM  = 0o7777;
     }
 }
 
-const AT_FDCWD: isize = -100;
+pub const AT_FDCWD: isize = -100;
+
+/// lseek 的 whence
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+/// mmap 的 prot 和 flags
+pub const PROT_READ: usize = 1;
+pub const PROT_WRITE: usize = 2;
+pub const PROT_EXEC: usize = 4;
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+/// msync 的 flags，内核不区分
+pub const MS_SYNC: usize = 4;
+const AT_REMOVEDIR: usize = 0x200;
+/// fstatat 的 flags：最后一级是符号链接的话不跟过去
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+/// mkdir 新建的目录的权限
+const DEFAULT_DIR_MODE: usize = 0o755;
 
 const USER_HEAP_SIZE: usize = 32768;
 
@@ -119,11 +173,16 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const EXCL = 1 << 7;
+        const APPEND = 1 << 11;
+        const DIRECTORY = 1 << 16;
+        const CLOEXEC = 1 << 19;
     }
 }
 
 pub fn dup(fd: usize) -> isize { sys_dup(fd) }
-pub fn open(path: &str, flags: OpenFlags) -> isize { sys_open(path, flags.bits) }
+pub fn open(path: &str, flags: OpenFlags) -> isize { sys_openat(AT_FDCWD as usize, path, flags.bits) }
+pub fn openat(dirfd: isize, path: &str, flags: OpenFlags) -> isize { sys_openat(dirfd as usize, path, flags.bits) }
 pub fn close(fd: usize) -> isize { sys_close(fd) }
 pub fn pipe(pipe_fd: &mut [usize]) -> isize { sys_pipe(pipe_fd) }
 pub fn read(fd: usize, buf: &mut [u8]) -> isize { sys_read(fd, buf) }
@@ -141,6 +200,11 @@ pub fn get_time() -> isize {
     }
 }
 pub fn getpid() -> isize { sys_getpid() }
+pub fn getuid() -> isize { sys_getuid() }
+pub fn getgid() -> isize { sys_getgid() }
+/// 只有 root 能换成别的 uid/gid，换掉以后就回不来了，所以要先 setgid 再 setuid
+pub fn setuid(uid: u32) -> isize { sys_setuid(uid) }
+pub fn setgid(gid: u32) -> isize { sys_setgid(gid) }
 pub fn fork() -> isize { sys_fork() }
 pub fn exec(path: &str, args: &[*const u8]) -> isize { 
     sys_exec(path, args) 
@@ -177,8 +241,16 @@ pub fn set_priority(prio: isize) -> isize {
 
 
 //=====================lab4===============================
-pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
-    sys_mmap(start, len, prot)
+pub fn mmap(start: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> isize {
+    sys_mmap(start, len, prot, flags, fd, offset)
+}
+
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+pub fn msync(start: usize, len: usize, flags: usize) -> isize {
+    sys_msync(start, len, flags)
 }
 
 pub fn munmap(start: usize, len: usize) -> isize {
@@ -214,4 +286,149 @@ pub fn unlink(path: &str) -> isize {
 
 pub fn fstat(fd: usize, st: &Stat) -> isize {
     sys_fstat(fd, st)
-}
\ No newline at end of file
+}
+
+/// 不用打开文件就能拿到它的状态
+pub fn stat(path: &str, st: &Stat) -> isize {
+    sys_fstatat(AT_FDCWD as usize, path, st, 0)
+}
+
+pub fn fstatat(dirfd: isize, path: &str, st: &Stat) -> isize {
+    sys_fstatat(dirfd as usize, path, st, 0)
+}
+
+/// 和 stat 一样，但是 path 本身是符号链接的话拿到的是链接自己的状态
+pub fn lstat(path: &str, st: &Stat) -> isize {
+    sys_fstatat(AT_FDCWD as usize, path, st, AT_SYMLINK_NOFOLLOW)
+}
+
+/// 新建符号链接 linkpath，指向 target，target 不需要存在
+pub fn symlink(target: &str, linkpath: &str) -> isize {
+    sys_symlinkat(target, AT_FDCWD as usize, linkpath)
+}
+
+/// 返回链接内容的长度，内容末尾没有 \0；path 不是符号链接返回 -1
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize {
+    sys_readlinkat(AT_FDCWD as usize, path, buf)
+}
+
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD as usize, path, DEFAULT_DIR_MODE)
+}
+
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD as usize, path, AT_REMOVEDIR)
+}
+
+pub fn mkdirat(dirfd: isize, path: &str) -> isize {
+    sys_mkdirat(dirfd as usize, path, DEFAULT_DIR_MODE)
+}
+
+/// 改权限位，只有文件的主人和 root 可以
+pub fn chmod(path: &str, mode: u32) -> isize {
+    sys_fchmodat(AT_FDCWD as usize, path, mode)
+}
+
+/// uid 或 gid 传 u32::MAX 表示不改；只有 root 能改属主
+pub fn chown(path: &str, uid: u32, gid: u32) -> isize {
+    sys_fchownat(AT_FDCWD as usize, path, uid, gid, 0)
+}
+
+pub fn linkat(old_dirfd: isize, old_path: &str, new_dirfd: isize, new_path: &str) -> isize {
+    sys_linkat(old_dirfd as usize, old_path, new_dirfd as usize, new_path, 0)
+}
+
+pub fn unlinkat(dirfd: isize, path: &str) -> isize {
+    sys_unlinkat(dirfd as usize, path, 0)
+}
+
+/// renameat2 的 flags：目标已经存在就失败
+pub const RENAME_NOREPLACE: u32 = 1;
+
+/// 改名或者移动，目标已经存在的话会被替换掉
+pub fn rename(old_path: &str, new_path: &str) -> isize {
+    sys_renameat2(AT_FDCWD as usize, old_path, AT_FDCWD as usize, new_path, 0)
+}
+
+pub fn renameat2(olddirfd: isize, old_path: &str, newdirfd: isize, new_path: &str, flags: u32) -> isize {
+    sys_renameat2(olddirfd as usize, old_path, newdirfd as usize, new_path, flags)
+}
+
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+
+/// 返回写入 buf 的长度（包括结尾的 \0），buf 不够长时返回 -1
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
+
+pub fn sync() -> isize {
+    sys_sync()
+}
+
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
+
+pub fn ftruncate(fd: usize, length: usize) -> isize {
+    sys_ftruncate(fd, length)
+}
+
+/// 返回新的读写位置，出错返回 -1
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
+/// 从 offset 处读，不改变 fd 的读写位置
+pub fn pread(fd: usize, buf: &mut [u8], offset: usize) -> isize {
+    sys_pread64(fd, buf, offset)
+}
+
+/// 写到 offset 处，不改变 fd 的读写位置
+pub fn pwrite(fd: usize, buf: &[u8], offset: usize) -> isize {
+    sys_pwrite64(fd, buf, offset)
+}
+
+/// getdents64 读出来的目录项的类型
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+
+/// 目录里的一项
+pub struct DirEntry {
+    pub ino: u64,
+    /// 下一项在目录里的位置
+    pub off: i64,
+    pub d_type: u8,
+    pub name: String,
+}
+
+/// 从目录 fd 里读目录项到 buf，返回读了多少字节，读完了返回 0，出错返回 -1
+pub fn getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+
+/// 把 getdents64 填好的缓冲区拆成一条一条的目录项
+pub fn parse_dirents(buf: &[u8]) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + 19 <= buf.len() {
+        let field = |start: usize, len: usize| {
+            let mut bytes = [0u8; 8];
+            bytes[..len].copy_from_slice(&buf[pos + start..pos + start + len]);
+            u64::from_le_bytes(bytes)
+        };
+        let reclen = field(16, 2) as usize;
+        let name = &buf[pos + 19..pos + reclen];
+        let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        entries.push(DirEntry {
+            ino: field(0, 8),
+            off: field(8, 8) as i64,
+            d_type: buf[pos + 18],
+            name: String::from(core::str::from_utf8(&name[..name_len]).unwrap()),
+        });
+        pos += reclen;
+    }
+    entries
+}
@@ -1,29 +1,51 @@
 use super::{Stat, TimeVal};
+const SYSCALL_GETCWD: usize = 17;
 const SYSCALL_DUP: usize = 24;
 const SYSCALL_OPENAT: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_GETDENTS64: usize = 61;
+const SYSCALL_LSEEK: usize = 62;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PREAD64: usize = 67;
+const SYSCALL_PWRITE64: usize = 68;
 
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_SETGID: usize = 144;
+const SYSCALL_SETUID: usize = 146;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETGID: usize = 176;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MSYNC: usize = 227;
 const SYSCALL_SPAWN: usize = 400;
 //=====================lab6===============================
 const SYSCALL_MAIL_READ: usize = 401;
 const SYSCALL_MAIL_WRITE: usize = 402;
 //=====================lab7===============================
+const SYSCALL_MKDIRAT: usize = 34;
 const SYSCALL_UNLINKAT: usize = 35;
+const SYSCALL_SYMLINKAT: usize = 36;
 const SYSCALL_LINKAT: usize = 37;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_FCHMODAT: usize = 53;
+const SYSCALL_FCHOWNAT: usize = 54;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_READLINKAT: usize = 78;
+const SYSCALL_FSTATAT: usize = 79;
+const SYSCALL_RENAMEAT2: usize = 276;
 const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -52,12 +74,26 @@ fn syscall5(id: usize, args: [usize; 5]) -> isize {
     ret
 }
 
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        llvm_asm!("ecall"
+            : "={x10}" (ret)
+            : "{x10}" (args[0]), "{x11}" (args[1]), "{x12}" (args[2]), "{x13}" (args[3]),
+                "{x14}" (args[4]), "{x15}" (args[5]), "{x17}" (id)
+            : "memory"
+            : "volatile"
+        );
+    }
+    ret
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
-pub fn sys_open(path: &str, flags: u32) -> isize {
-    syscall(SYSCALL_OPENAT, [path.as_ptr() as usize, flags as usize, 0])
+pub fn sys_openat(dirfd: usize, path: &str, flags: u32) -> isize {
+    syscall(SYSCALL_OPENAT, [dirfd, path.as_ptr() as usize, flags as usize])
 }
 
 pub fn sys_close(fd: usize) -> isize {
@@ -96,6 +132,22 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getuid() -> isize {
+    syscall(SYSCALL_GETUID, [0, 0, 0])
+}
+
+pub fn sys_getgid() -> isize {
+    syscall(SYSCALL_GETGID, [0, 0, 0])
+}
+
+pub fn sys_setuid(uid: u32) -> isize {
+    syscall(SYSCALL_SETUID, [uid as usize, 0, 0])
+}
+
+pub fn sys_setgid(gid: u32) -> isize {
+    syscall(SYSCALL_SETGID, [gid as usize, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -113,8 +165,16 @@ pub fn sys_set_priority(prio: isize) -> isize {
 }
 
 //=====================lab4===============================
-pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
-    syscall(SYSCALL_MMAP, [start, len, prot])
+pub fn sys_mmap(start: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> isize {
+    syscall6(SYSCALL_MMAP, [start, len, prot, flags, fd as usize, offset])
+}
+
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_msync(start: usize, len: usize, flags: usize) -> isize {
+    syscall(SYSCALL_MSYNC, [start, len, flags])
 }
 
 pub fn sys_munmap(start: usize, len: usize) -> isize {
@@ -167,6 +227,70 @@ pub fn sys_unlinkat(dirfd: usize, path: &str, flags: usize) -> isize {
     syscall(SYSCALL_UNLINKAT, [dirfd, path.as_ptr() as usize, flags])
 }
 
+pub fn sys_symlinkat(target: &str, newdirfd: usize, linkpath: &str) -> isize {
+    syscall(SYSCALL_SYMLINKAT, [target.as_ptr() as usize, newdirfd, linkpath.as_ptr() as usize])
+}
+
+pub fn sys_readlinkat(dirfd: usize, path: &str, buf: &mut [u8]) -> isize {
+    syscall5(SYSCALL_READLINKAT, [dirfd, path.as_ptr() as usize, buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
 pub fn sys_fstat(fd: usize, st: &Stat) -> isize {
     syscall(SYSCALL_FSTAT, [fd, st as *const _ as usize, 0])
-}
\ No newline at end of file
+}
+
+pub fn sys_fstatat(dirfd: usize, path: &str, st: &Stat, flags: u32) -> isize {
+    syscall5(SYSCALL_FSTATAT, [dirfd, path.as_ptr() as usize, st as *const _ as usize, flags as usize, 0])
+}
+
+pub fn sys_fchmodat(dirfd: usize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_FCHMODAT, [dirfd, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_fchownat(dirfd: usize, path: &str, uid: u32, gid: u32, flags: u32) -> isize {
+    syscall5(SYSCALL_FCHOWNAT, [dirfd, path.as_ptr() as usize, uid as usize, gid as usize, flags as usize])
+}
+
+pub fn sys_mkdirat(dirfd: usize, path: &str, mode: usize) -> isize {
+    syscall(SYSCALL_MKDIRAT, [dirfd, path.as_ptr() as usize, mode])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_getcwd(buffer: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETCWD, [buffer.as_mut_ptr() as usize, buffer.len(), 0])
+}
+
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYSCALL_FSYNC, [fd, 0, 0])
+}
+
+pub fn sys_ftruncate(fd: usize, length: usize) -> isize {
+    syscall(SYSCALL_FTRUNCATE, [fd, length, 0])
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
+}
+
+pub fn sys_pread64(fd: usize, buffer: &mut [u8], offset: usize) -> isize {
+    syscall5(SYSCALL_PREAD64, [fd, buffer.as_mut_ptr() as usize, buffer.len(), offset, 0])
+}
+
+pub fn sys_pwrite64(fd: usize, buffer: &[u8], offset: usize) -> isize {
+    syscall5(SYSCALL_PWRITE64, [fd, buffer.as_ptr() as usize, buffer.len(), offset, 0])
+}
+
+pub fn sys_getdents64(fd: usize, buffer: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETDENTS64, [fd, buffer.as_mut_ptr() as usize, buffer.len()])
+}
+
+pub fn sys_renameat2(olddirfd: usize, oldpath: &str, newdirfd: usize, newpath: &str, flags: u32) -> isize {
+    syscall5(SYSCALL_RENAMEAT2, [olddirfd, oldpath.as_ptr() as usize, newdirfd, newpath.as_ptr() as usize, flags as usize])
+}